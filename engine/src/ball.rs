@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BallMovingStatus {
+    Running,
+    Backing,
+    Done,
+    /// a ball from a split that reached the floor
+    Gone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BallStatus {
+    pub x: f64,
+    pub y: f64,
+    /// unit vector of the moving direction
+    #[serde(default)]
+    pub vx: f64,
+    #[serde(default)]
+    pub vy: f64,
+    pub moving_status: BallMovingStatus,
+    /// made by a split, does not count in `n_balls`
+    pub is_temporary: bool,
+}
+
+impl BallStatus {
+    pub(crate) fn launched(x: f64, y: f64, vx: f64, vy: f64) -> Self {
+        Self {
+            x,
+            y,
+            vx,
+            vy,
+            moving_status: BallMovingStatus::Running,
+            is_temporary: false,
+        }
+    }
+}
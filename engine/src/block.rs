@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+/// Shape of a block, triangles are named after the corner with the right
+/// angle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockShape {
+    #[default]
+    Square,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl BlockShape {
+    pub const TRIANGLES: [Self; 4] = [
+        Self::TopLeft,
+        Self::TopRight,
+        Self::BottomLeft,
+        Self::BottomRight,
+    ];
+
+    /// Corners of this shape in the cell `[x0, x1] × [y0, y1]`, clockwise on
+    /// screen.
+    pub fn corners(self, (x0, y0, x1, y1): (f64, f64, f64, f64)) -> Vec<(f64, f64)> {
+        match self {
+            Self::Square => vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)],
+            Self::TopLeft => vec![(x0, y0), (x1, y0), (x0, y1)],
+            Self::TopRight => vec![(x0, y0), (x1, y0), (x1, y1)],
+            Self::BottomLeft => vec![(x0, y0), (x1, y1), (x0, y1)],
+            Self::BottomRight => vec![(x1, y0), (x1, y1), (x0, y1)],
+        }
+    }
+}
+
+/// Which lines a laser fires along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Laser {
+    Row,
+    Column,
+    Cross,
+}
+
+/// What is in a cell of the map.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cell {
+    #[default]
+    Empty,
+    /// breaks after `hp` hits
+    Block { hp: i32 },
+    /// only every second hit counts, `cracked` once the first of the two
+    /// is taken
+    Armored { hp: i32, cracked: bool },
+    /// never breaks, and is removed when it reaches the bottom instead of
+    /// ending the game
+    Steel,
+    /// a ball to get
+    BallPickup,
+    /// damages every block in its row, column or both each time a ball goes
+    /// through it, and is gone at the end of the turn
+    Laser(Laser),
+    /// splits a ball going through it into [`SPLIT_BALLS`](crate::SPLIT_BALLS)
+    /// more, which are gone once they reach the floor
+    Split,
+    /// sends a ball going through it in a random direction
+    Bounce,
+}
+
+impl Cell {
+    /// Balls bounce on it.
+    pub fn is_solid(self) -> bool {
+        matches!(
+            self,
+            Self::Block { .. } | Self::Armored { .. } | Self::Steel
+        )
+    }
+
+    /// Ends the game when it reaches the bottom. Solid cells that are not
+    /// are removed there instead.
+    pub fn is_lethal(self) -> bool {
+        matches!(self, Self::Block { .. } | Self::Armored { .. })
+    }
+
+    /// Does something when a ball goes through it, and is gone at the end of
+    /// the turn if it did.
+    pub fn is_trigger(self) -> bool {
+        matches!(self, Self::Laser(_) | Self::Split | Self::Bounce)
+    }
+
+    /// Hits left before it breaks, for the cells that can break.
+    pub fn hp(self) -> Option<i32> {
+        match self {
+            Self::Block { hp } | Self::Armored { hp, .. } => Some(hp),
+            _ => None,
+        }
+    }
+
+    /// What is left after one hit by a ball or a laser.
+    pub fn on_hit(self) -> Self {
+        match self {
+            Self::Block { hp } if hp > 1 => Self::Block { hp: hp - 1 },
+            Self::Block { .. } => Self::Empty,
+            Self::Armored { hp, cracked: false } => Self::Armored { hp, cracked: true },
+            Self::Armored { hp, cracked: true } if hp > 1 => Self::Armored {
+                hp: hp - 1,
+                cracked: false,
+            },
+            Self::Armored { .. } => Self::Empty,
+            other => other,
+        }
+    }
+}
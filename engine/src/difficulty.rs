@@ -0,0 +1,134 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// How new rows get harder with the level.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyProfile {
+    /// a row has at least one more block every this many levels
+    pub min_blocks_every: u32,
+    /// a row may have one more block every this many levels
+    pub max_blocks_every: u32,
+    /// hp of new blocks is the level times this
+    pub hp_multiplier: f64,
+    /// every this many levels the row has double hp, never if `None`
+    pub double_hp_every: Option<u32>,
+    /// chance for a new row to have a ball to get
+    pub pickup_chance: f64,
+}
+
+impl DifficultyProfile {
+    pub const EASY: Self = Self {
+        min_blocks_every: 20,
+        max_blocks_every: 8,
+        hp_multiplier: 0.75,
+        double_hp_every: None,
+        pickup_chance: 0.9,
+    };
+
+    /// How rows were made before difficulties could be chosen.
+    pub const NORMAL: Self = Self {
+        min_blocks_every: 15,
+        max_blocks_every: 6,
+        hp_multiplier: 1.0,
+        double_hp_every: None,
+        pickup_chance: 0.8,
+    };
+
+    pub const HARD: Self = Self {
+        min_blocks_every: 10,
+        max_blocks_every: 4,
+        hp_multiplier: 1.25,
+        double_hp_every: Some(10),
+        pickup_chance: 0.65,
+    };
+
+    pub const PRESETS: [(&'static str, Self); 3] = [
+        ("easy", Self::EASY),
+        ("normal", Self::NORMAL),
+        ("hard", Self::HARD),
+    ];
+
+    /// Name of the preset it is, if any.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Self::PRESETS
+            .iter()
+            .find(|(_, preset)| preset == self)
+            .map(|(name, _)| *name)
+    }
+
+    /// Keeps every value in a range rows can be made with.
+    pub fn clamped(self) -> Self {
+        Self {
+            min_blocks_every: self.min_blocks_every.max(1),
+            max_blocks_every: self.max_blocks_every.max(1),
+            hp_multiplier: match self.hp_multiplier {
+                hp if hp.is_finite() => hp.clamp(0.1, 10.0),
+                _ => 1.0,
+            },
+            double_hp_every: self.double_hp_every.filter(|&every| every > 0),
+            pickup_chance: match self.pickup_chance {
+                p if p.is_finite() => p.clamp(0.0, 1.0),
+                _ => Self::NORMAL.pickup_chance,
+            },
+        }
+    }
+
+    /// Hp of the blocks of the row made at level `n_rank`.
+    pub fn row_hp(&self, n_rank: usize) -> i32 {
+        let hp = (n_rank as f64 * self.hp_multiplier).round().max(1.0) as i32;
+        match self.double_hp_every {
+            Some(every) if (n_rank as u32).is_multiple_of(every) => hp * 2,
+            _ => hp,
+        }
+    }
+}
+
+impl Default for DifficultyProfile {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+/// Written as
+/// `min_blocks_every,max_blocks_every,hp_multiplier,double_hp_every,pickup_chance`,
+/// `double_hp_every` is empty for never.
+impl fmt::Display for DifficultyProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{},{}",
+            self.min_blocks_every,
+            self.max_blocks_every,
+            self.hp_multiplier,
+            self.double_hp_every
+                .map_or(String::new(), |every| every.to_string()),
+            self.pickup_chance,
+        )
+    }
+}
+
+impl FromStr for DifficultyProfile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [min_blocks_every, max_blocks_every, hp_multiplier, double_hp_every, pickup_chance] =
+            s.split(',').collect::<Vec<_>>()[..]
+        else {
+            return Err(());
+        };
+        let profile = Self {
+            min_blocks_every: min_blocks_every.parse().map_err(|_| ())?,
+            max_blocks_every: max_blocks_every.parse().map_err(|_| ())?,
+            hp_multiplier: hp_multiplier.parse().map_err(|_| ())?,
+            double_hp_every: match double_hp_every {
+                "" => None,
+                every => Some(every.parse().map_err(|_| ())?),
+            },
+            pickup_chance: pickup_chance.parse().map_err(|_| ())?,
+        };
+        // 不接受会被改掉的值，不然回放就不是原来那局了
+        (profile.clamped() == profile).then_some(profile).ok_or(())
+    }
+}
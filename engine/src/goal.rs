@@ -0,0 +1,42 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// What a [`crate::Layout`] has to be played for, checked at the end of
+/// every turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Goal {
+    /// no block left on the board
+    Clear,
+    /// this many turns played
+    Survive(u32),
+    /// this many balls
+    Collect(u32),
+}
+
+/// Written as `clear`, `survive N` or `collect N`.
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Clear => f.write_str("clear"),
+            Self::Survive(turns) => write!(f, "survive {turns}"),
+            Self::Collect(balls) => write!(f, "collect {balls}"),
+        }
+    }
+}
+
+impl FromStr for Goal {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, n) = s.split_once(' ').unwrap_or((s, ""));
+        let n = || n.trim().parse().ok().filter(|n| *n > 0).ok_or(());
+        match kind {
+            "clear" => Ok(Self::Clear),
+            "survive" => Ok(Self::Survive(n()?)),
+            "collect" => Ok(Self::Collect(n()?)),
+            _ => Err(()),
+        }
+    }
+}
@@ -0,0 +1,577 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BlockShape, Cell, GameState, Goal, Laser, BLOCK_SIZE};
+
+/// A board made by hand, to start a game from instead of random rows.
+///
+/// Written as a few `key value` header lines followed by the grid, one line
+/// per row from top to bottom, and optionally by `next` and rows to push at
+/// the top on the next turns, before random ones come again:
+///
+/// ```text
+/// size 10x16
+/// balls 3
+/// start 550
+/// seed 42
+/// . . 12 . o . . . . .
+/// ...
+/// next
+/// 20 20 . . . . . . 20 20
+/// ```
+///
+/// `size` comes first, the other header lines can be left out. With
+/// `shots N` the layout is a puzzle: no rows come, the board has to be
+/// cleared in `N` shots, and three stars are for `par P` shots or less.
+/// `goal` sets a [`Goal`] that ends the game as won once reached.
+///
+/// Cells are separated by spaces: `.` is empty, a number a block with that
+/// many hits left, `12tl`, `12tr`, `12bl` or `12br` a triangle with the
+/// right angle in that corner, `a12` an armored block (`A12` once cracked),
+/// `#` steel, `o` a ball to get, `h`, `v` and `x` lasers along the row, the
+/// column or both, `s` a split and `?` a random bounce. The bottom row is
+/// where balls start from and cannot have blocks. `//` starts a comment up
+/// to the end of the line, and `/` also ends a line so that a layout fits
+/// on one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layout {
+    pub mw: usize,
+    pub mh: usize,
+    pub n_balls: u32,
+    pub start_x: f64,
+    /// seed of the random rows, the game's own if `None`
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// shots to clear the board in, a puzzle if set
+    #[serde(default)]
+    pub max_shots: Option<u32>,
+    /// shots to clear the puzzle in for three stars
+    #[serde(default)]
+    pub par: Option<u32>,
+    /// [`Goal::Clear`] for puzzles if not set
+    #[serde(default)]
+    pub goal: Option<Goal>,
+    /// `mh` rows of `mw` cells, from top to bottom
+    pub cells: Vec<Vec<Cell>>,
+    /// shapes of the blocks in `cells`
+    pub shapes: Vec<Vec<BlockShape>>,
+    /// rows pushed on the next turns, the first one after the first shot
+    #[serde(default)]
+    pub next_rows: Vec<Vec<Cell>>,
+    /// shapes of the blocks in `next_rows`
+    #[serde(default)]
+    pub next_shapes: Vec<Vec<BlockShape>>,
+}
+
+impl Layout {
+    /// An empty `mw`×`mh` board with one ball in the middle.
+    pub fn empty(mw: usize, mh: usize) -> Self {
+        Self {
+            mw,
+            mh,
+            n_balls: 1,
+            start_x: mw as f64 * BLOCK_SIZE / 2.0,
+            seed: None,
+            max_shots: None,
+            par: None,
+            goal: None,
+            cells: vec![vec![Cell::Empty; mw]; mh],
+            shapes: vec![vec![BlockShape::Square; mw]; mh],
+            next_rows: vec![],
+            next_shapes: vec![],
+        }
+    }
+
+    /// The board of `state` as it is now, random rows come next.
+    pub fn from_state(state: &GameState) -> Self {
+        Self {
+            mw: state.mw,
+            mh: state.mh,
+            n_balls: state.n_balls,
+            start_x: state.start_x,
+            seed: None,
+            max_shots: None,
+            par: None,
+            goal: None,
+            next_rows: vec![],
+            next_shapes: vec![],
+            cells: state.block_map.iter().cloned().collect(),
+            // 打掉的块会留下形状
+            shapes: (0..state.mh)
+                .map(|i| {
+                    (0..state.mw)
+                        .map(|j| match state.block_map[i][j] {
+                            Cell::Block { .. } => state.block_shape(i, j),
+                            _ => BlockShape::Square,
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+const HEADER_KEYS: [&str; 7] = ["size", "balls", "start", "seed", "shots", "par", "goal"];
+
+fn cell_token(cell: Cell, shape: BlockShape) -> String {
+    match cell {
+        Cell::Empty => String::from("."),
+        Cell::Block { hp } => {
+            let corner = match shape {
+                BlockShape::Square => "",
+                BlockShape::TopLeft => "tl",
+                BlockShape::TopRight => "tr",
+                BlockShape::BottomLeft => "bl",
+                BlockShape::BottomRight => "br",
+            };
+            format!("{hp}{corner}")
+        }
+        Cell::Armored { hp, cracked } => format!("{}{hp}", if cracked { "A" } else { "a" }),
+        Cell::Steel => String::from("#"),
+        Cell::BallPickup => String::from("o"),
+        Cell::Laser(Laser::Row) => String::from("h"),
+        Cell::Laser(Laser::Column) => String::from("v"),
+        Cell::Laser(Laser::Cross) => String::from("x"),
+        Cell::Split => String::from("s"),
+        Cell::Bounce => String::from("?"),
+    }
+}
+
+fn parse_cell(token: &str) -> Option<(Cell, BlockShape)> {
+    let hp = |s: &str| s.parse::<i32>().ok().filter(|hp| *hp > 0);
+    let cell = match token {
+        "." => Cell::Empty,
+        "#" => Cell::Steel,
+        "o" => Cell::BallPickup,
+        "h" => Cell::Laser(Laser::Row),
+        "v" => Cell::Laser(Laser::Column),
+        "x" => Cell::Laser(Laser::Cross),
+        "s" => Cell::Split,
+        "?" => Cell::Bounce,
+        _ => {
+            if let Some(rest) = token.strip_prefix('a') {
+                Cell::Armored {
+                    hp: hp(rest)?,
+                    cracked: false,
+                }
+            } else if let Some(rest) = token.strip_prefix('A') {
+                Cell::Armored {
+                    hp: hp(rest)?,
+                    cracked: true,
+                }
+            } else {
+                let digits = token.find(|c: char| !c.is_ascii_digit());
+                let (number, corner) = token.split_at(digits.unwrap_or(token.len()));
+                let shape = match corner {
+                    "" => BlockShape::Square,
+                    "tl" => BlockShape::TopLeft,
+                    "tr" => BlockShape::TopRight,
+                    "bl" => BlockShape::BottomLeft,
+                    "br" => BlockShape::BottomRight,
+                    _ => return None,
+                };
+                return Some((Cell::Block { hp: hp(number)? }, shape));
+            }
+        }
+    };
+    Some((cell, BlockShape::Square))
+}
+
+fn write_rows(
+    f: &mut fmt::Formatter<'_>,
+    rows: &[Vec<Cell>],
+    shapes: &[Vec<BlockShape>],
+) -> fmt::Result {
+    for (i, row) in rows.iter().enumerate() {
+        let tokens: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(j, cell)| {
+                let shape = shapes.get(i).and_then(|row| row.get(j)).copied();
+                cell_token(*cell, shape.unwrap_or_default())
+            })
+            .collect();
+        writeln!(f, "{}", tokens.join(" "))?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size {}x{}", self.mw, self.mh)?;
+        writeln!(f, "balls {}", self.n_balls)?;
+        writeln!(f, "start {}", self.start_x)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed {seed}")?;
+        }
+        if let Some(max_shots) = self.max_shots {
+            writeln!(f, "shots {max_shots}")?;
+        }
+        if let Some(par) = self.par {
+            writeln!(f, "par {par}")?;
+        }
+        if let Some(goal) = self.goal {
+            writeln!(f, "goal {goal}")?;
+        }
+        write_rows(f, &self.cells, &self.shapes)?;
+        if !self.next_rows.is_empty() {
+            writeln!(f, "next")?;
+            write_rows(f, &self.next_rows, &self.next_shapes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a line of a layout is in the text: the line, counted from 1, and
+/// the piece of it between `/`s, only if it has more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinePos {
+    pub line: usize,
+    pub part: Option<usize>,
+}
+
+impl fmt::Display for LinePos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(part) = self.part {
+            write!(f, ", part {part}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseLayoutError {
+    /// the grid starts before any `size` line
+    MissingSize,
+    /// a header line with an unknown key
+    UnknownKey { line: LinePos, key: String },
+    /// a header line whose value does not parse
+    BadValue { line: LinePos, key: &'static str },
+    /// an unknown cell, `column` counts from 1
+    Cell {
+        line: LinePos,
+        column: usize,
+        token: String,
+    },
+    /// a row without `mw` cells
+    RowLength {
+        line: LinePos,
+        len: usize,
+        mw: usize,
+    },
+    /// a grid without `mh` rows
+    RowCount { len: usize, mh: usize },
+    /// a block in the bottom row of the grid, where balls start from
+    SolidBottom { line: LinePos, column: usize },
+}
+
+impl fmt::Display for ParseLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSize => f.write_str("missing `size WxH` line before the grid"),
+            Self::UnknownKey { line, key } => write!(
+                f,
+                "{line}: unknown `{key}`, expected size, balls, start, seed, shots, par or goal"
+            ),
+            Self::BadValue { line, key } => {
+                let expected = match *key {
+                    "size" => "`size WxH`, both at least 4",
+                    "start" => "a number",
+                    "goal" => "`clear`, `survive N` or `collect N`",
+                    "balls" | "shots" | "par" => "a whole number above 0",
+                    _ => "a whole number",
+                };
+                write!(f, "{line}: invalid {key}, expected {expected}")
+            }
+            Self::Cell {
+                line,
+                column,
+                token,
+            } => write!(f, "{line}, cell {column}: unknown cell `{token}`"),
+            Self::RowLength { line, len, mw } => {
+                write!(f, "{line}: {len} cells, expected {mw}")
+            }
+            Self::RowCount { len, mh } => write!(f, "{len} rows in the grid, expected {mh}"),
+            Self::SolidBottom { line, column } => write!(
+                f,
+                "{line}, cell {column}: no blocks in the bottom row, balls start from there"
+            ),
+        }
+    }
+}
+
+impl Error for ParseLayoutError {}
+
+fn parse_row(
+    n: LinePos,
+    line: &str,
+    mw: usize,
+) -> Result<(Vec<Cell>, Vec<BlockShape>), ParseLayoutError> {
+    let (row, shapes): (Vec<Cell>, Vec<BlockShape>) = line
+        .split_whitespace()
+        .enumerate()
+        .map(|(j, token)| {
+            parse_cell(token).ok_or_else(|| ParseLayoutError::Cell {
+                line: n,
+                column: j + 1,
+                token: token.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    if row.len() != mw {
+        return Err(ParseLayoutError::RowLength {
+            line: n,
+            len: row.len(),
+            mw,
+        });
+    }
+    Ok((row, shapes))
+}
+
+impl FromStr for Layout {
+    type Err = ParseLayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut size = None;
+        let mut n_balls = 1;
+        let mut start_x = None;
+        let mut seed = None;
+        let mut max_shots = None;
+        let mut par = None;
+        let mut goal = None;
+        let mut cells = vec![];
+        let mut shapes = vec![];
+        let mut next_rows = vec![];
+        let mut next_shapes = vec![];
+        let mut in_next = false;
+
+        // 报错用原文的行号，一行里有 / 再加上是第几段
+        let lines = s
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                // 注释先去掉，里面的 / 不算
+                let line = line.split_once("//").map_or(line, |(line, _)| line);
+                let parts: Vec<&str> = line.split('/').collect();
+                let is_split = parts.len() > 1;
+                parts.into_iter().enumerate().map(move |(k, part)| {
+                    let pos = LinePos {
+                        line: i + 1,
+                        part: is_split.then_some(k + 1),
+                    };
+                    (pos, part.trim())
+                })
+            })
+            .filter(|(_, line)| !line.is_empty());
+        for (n, line) in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            // 表头在前，格子在后
+            if let Some(&key) = HEADER_KEYS.iter().find(|k| **k == key && cells.is_empty()) {
+                let value = value.trim();
+                let ok = match key {
+                    "size" => value
+                        .split_once('x')
+                        .and_then(|(mw, mh)| Some((mw.parse().ok()?, mh.parse().ok()?)))
+                        .filter(|&(mw, mh): &(usize, usize)| mw >= 4 && mh >= 4)
+                        .map(|s| size = Some(s))
+                        .is_some(),
+                    "balls" => value
+                        .parse()
+                        .ok()
+                        .filter(|b| *b > 0)
+                        .map(|b| n_balls = b)
+                        .is_some(),
+                    "start" => value
+                        .parse()
+                        .ok()
+                        .filter(|x: &f64| x.is_finite())
+                        .map(|x| start_x = Some(x))
+                        .is_some(),
+                    "seed" => value.parse().map(|s| seed = Some(s)).is_ok(),
+                    "shots" => value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .map(|n| max_shots = Some(n))
+                        .is_some(),
+                    "par" => value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .map(|n| par = Some(n))
+                        .is_some(),
+                    _ => value.parse().map(|g| goal = Some(g)).is_ok(),
+                };
+                if !ok {
+                    return Err(ParseLayoutError::BadValue { line: n, key });
+                }
+                continue;
+            }
+            if size.is_none() && parse_cell(key).is_none() {
+                return Err(ParseLayoutError::UnknownKey {
+                    line: n,
+                    key: key.to_string(),
+                });
+            }
+
+            let (mw, mh) = size.ok_or(ParseLayoutError::MissingSize)?;
+            if line == "next" && !in_next {
+                if cells.len() != mh {
+                    return Err(ParseLayoutError::RowCount {
+                        len: cells.len(),
+                        mh,
+                    });
+                }
+                in_next = true;
+                continue;
+            }
+            let (row, row_shapes) = parse_row(n, line, mw)?;
+            if !in_next && cells.len() + 1 == mh {
+                if let Some(j) = row.iter().position(|cell| cell.is_solid()) {
+                    return Err(ParseLayoutError::SolidBottom {
+                        line: n,
+                        column: j + 1,
+                    });
+                }
+            }
+            if in_next {
+                next_rows.push(row);
+                next_shapes.push(row_shapes);
+            } else {
+                cells.push(row);
+                shapes.push(row_shapes);
+            }
+        }
+
+        let (mw, mh) = size.ok_or(ParseLayoutError::MissingSize)?;
+        if cells.len() != mh {
+            return Err(ParseLayoutError::RowCount {
+                len: cells.len(),
+                mh,
+            });
+        }
+        let empty = Self::empty(mw, mh);
+        Ok(Self {
+            n_balls,
+            start_x: start_x.unwrap_or(empty.start_x),
+            seed,
+            max_shots,
+            par,
+            goal,
+            cells,
+            shapes,
+            next_rows,
+            next_shapes,
+            ..empty
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLED: [&str; 14] = [
+        include_str!("../../puzzles/01-warm-up.txt"),
+        include_str!("../../puzzles/02-pockets.txt"),
+        include_str!("../../puzzles/03-funnel.txt"),
+        include_str!("../../puzzles/04-steel-gate.txt"),
+        include_str!("../../puzzles/05-lasers.txt"),
+        include_str!("../../puzzles/06-fortress.txt"),
+        include_str!("../../campaign/01-first-rows.txt"),
+        include_str!("../../campaign/02-gather.txt"),
+        include_str!("../../campaign/03-clean-sweep.txt"),
+        include_str!("../../campaign/04-angles.txt"),
+        include_str!("../../campaign/05-sparks.txt"),
+        include_str!("../../campaign/06-iron-wall.txt"),
+        include_str!("../../campaign/07-long-haul.txt"),
+        include_str!("../../campaign/08-finale.txt"),
+    ];
+
+    fn err(s: &str) -> ParseLayoutError {
+        s.parse::<Layout>().unwrap_err()
+    }
+
+    fn at(line: usize, part: Option<usize>) -> LinePos {
+        LinePos { line, part }
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in BUNDLED {
+            let layout: Layout = text.parse().unwrap();
+            assert_eq!(layout.to_string().parse::<Layout>().unwrap(), layout);
+            // 链接里是一行
+            let one_line = layout.to_string().trim_end().replace('\n', "/");
+            assert_eq!(one_line.parse::<Layout>().unwrap(), layout);
+        }
+    }
+
+    #[test]
+    fn split_and_comments() {
+        let lines = "// two balls\nsize 4x4\nballs 2\n. 3tl . .\n. . . .\n. . . .\n. . . .";
+        let layout: Layout = lines.parse().unwrap();
+        assert_eq!(layout.n_balls, 2);
+        assert_eq!(layout.shapes[0][1], BlockShape::TopLeft);
+        // 注释里的 / 不分行
+        let one_line =
+            "size 4x4 / balls 2 // two/three\n. 3tl . . / . . . . / . . . . / . . . . // a/b";
+        assert_eq!(one_line.parse::<Layout>().unwrap(), layout);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(err(". . . ."), ParseLayoutError::MissingSize);
+        assert_eq!(
+            err("hello"),
+            ParseLayoutError::UnknownKey {
+                line: at(1, None),
+                key: String::from("hello"),
+            }
+        );
+        assert_eq!(
+            err("size 4x4 / balls x"),
+            ParseLayoutError::BadValue {
+                line: at(1, Some(2)),
+                key: "balls",
+            }
+        );
+        assert_eq!(
+            err("size 4x4\n// grid\n. . . . / . . z ."),
+            ParseLayoutError::Cell {
+                line: at(3, Some(2)),
+                column: 3,
+                token: String::from("z"),
+            }
+        );
+        assert_eq!(
+            err("size 4x4\n. . ."),
+            ParseLayoutError::RowLength {
+                line: at(2, None),
+                len: 3,
+                mw: 4,
+            }
+        );
+        assert_eq!(
+            err("size 4x4\n. . . ."),
+            ParseLayoutError::RowCount { len: 1, mh: 4 }
+        );
+        assert_eq!(
+            err("size 4x4\n. . . .\n. . . .\n. . . .\n. 3 . ."),
+            ParseLayoutError::SolidBottom {
+                line: at(5, None),
+                column: 2,
+            }
+        );
+        assert_eq!(
+            err("size 4x4 / balls x").to_string(),
+            "line 1, part 2: invalid balls, expected a whole number above 0"
+        );
+    }
+}
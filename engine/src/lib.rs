@@ -0,0 +1,60 @@
+//! Rules of bouncy ball without any rendering: block generation, ball
+//! movement and the turn flow.
+//!
+//! Nothing here depends on the browser, so the engine builds on native
+//! targets and the web front end only has to draw what [`GameState`] holds.
+
+mod ball;
+mod block;
+mod difficulty;
+mod goal;
+mod layout;
+mod physics;
+mod replay;
+mod rules;
+mod state;
+
+use serde::{Deserialize, Serialize};
+
+pub use ball::{BallMovingStatus, BallStatus};
+pub use block::{BlockShape, Cell, Laser};
+pub use difficulty::DifficultyProfile;
+pub use goal::Goal;
+pub use layout::{Layout, LinePos, ParseLayoutError};
+pub use replay::{ParseReplayError, Replay};
+pub use rules::{Rules, SpecialWeights, OBSTACLE_LEVEL, SPECIAL_CHANCE};
+pub use state::{daily_seed, GameState, StepOutcome, DAILY_SIZE};
+
+pub const BALL_SIZE: f64 = 36.0;
+pub const BALL_R: f64 = BALL_SIZE / 2.0;
+pub const BLOCK_SIZE: f64 = 100.0;
+
+/// how far a running ball travels in one [`GameState::step`]
+pub const STEP_DIST: f64 = 4.0;
+pub const NEXT_BALL_TIME_DIST: f64 = 3.0 * BALL_SIZE;
+
+pub const SPLIT_BALLS: u32 = 2;
+
+const EPS: f64 = 1e-10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameMode {
+    /// a new row every turn until the blocks reach the bottom
+    #[default]
+    Endless,
+    /// clear a fixed board in a limited number of shots, see
+    /// [`Layout::max_shots`]
+    Puzzle,
+    /// endless on the board of the day, see [`GameState::daily`]
+    Daily,
+}
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Endless => "endless",
+            Self::Puzzle => "puzzle",
+            Self::Daily => "daily",
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::f64::consts::PI;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::state::direction;
+use crate::{
+    BallMovingStatus, BallStatus, Cell, GameState, Laser, BALL_R, BLOCK_SIZE, EPS, SPLIT_BALLS,
+};
+
+/// 太平的球要很久才落地，反弹后竖直方向至少留这么多
+const MIN_VY: f64 = 0.05;
+
+/// angle between the balls coming out of a split
+const SPLIT_SPREAD: f64 = 0.25;
+
+/// random bounces use the streams from here on, rows use the low ones
+const BOUNCE_STREAM: u64 = 1 << 63;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Wall,
+    Floor,
+    Block(usize, usize),
+}
+
+/// Where a moving ball first touches something.
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    /// distance travelled before touching
+    t: f64,
+    /// unit normal of the touched surface, pointing to the ball
+    nx: f64,
+    ny: f64,
+    target: Target,
+}
+
+/// First contact of a ball at `(x, y)` moving along the unit vector `(vx, vy)`
+/// for at most `len` with the convex block with these `corners`, as
+/// `(t, nx, ny)`.
+///
+/// The ball touches the block when its center reaches the block grown by
+/// `BALL_R`, a polygon with rounded corners: sides are hit with the side's
+/// normal, corners with the normal from the corner to the ball center.
+fn sweep_polygon(
+    (x, y): (f64, f64),
+    (vx, vy): (f64, f64),
+    len: f64,
+    corners: &[(f64, f64)],
+) -> Option<(f64, f64, f64)> {
+    let mut best: Option<(f64, f64, f64)> = None;
+    let mut consider = |t: f64, nx: f64, ny: f64| {
+        // 只算迎面撞上的，刚反弹走的不会再撞一次
+        if (-EPS..=len).contains(&t)
+            && vx * nx + vy * ny < 0.0
+            && best.is_none_or(|(best_t, _, _)| t < best_t)
+        {
+            best = Some((t.max(0.0), nx, ny));
+        }
+    };
+
+    let n = corners.len() as f64;
+    let (gx, gy) = corners
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (cx, cy)| (sx + cx / n, sy + cy / n));
+    for (k, &(ax, ay)) in corners.iter().enumerate() {
+        let (bx, by) = corners[(k + 1) % corners.len()];
+        let (ex, ey) = (bx - ax, by - ay);
+        let l = ex.hypot(ey);
+        // 朝外的法向
+        let (mut nx, mut ny) = (ey / l, -ex / l);
+        if nx * (ax - gx) + ny * (ay - gy) < 0.0 {
+            (nx, ny) = (-nx, -ny);
+        }
+        let dn = vx * nx + vy * ny;
+        if dn >= 0.0 {
+            continue;
+        }
+        let t = ((x - ax) * nx + (y - ay) * ny - BALL_R) / -dn;
+        let s = ((x + vx * t - ax) * ex + (y + vy * t - ay) * ey) / (l * l);
+        if (0.0..=1.0).contains(&s) {
+            consider(t, nx, ny);
+        }
+    }
+
+    for &(cx, cy) in corners {
+        let (mx, my) = (x - cx, y - cy);
+        let b = mx * vx + my * vy;
+        let disc = b * b - (mx * mx + my * my - BALL_R * BALL_R);
+        if disc < 0.0 {
+            continue;
+        }
+        let t = -b - disc.sqrt();
+        consider(t, (mx + vx * t) / BALL_R, (my + vy * t) / BALL_R);
+    }
+
+    best
+}
+
+/// Whether the center of a ball at `(x, y)` moving along `(vx, vy)` for `len`
+/// passes through `[x0, x1] × [y0, y1]`.
+fn crosses_rect(
+    (x, y): (f64, f64),
+    (vx, vy): (f64, f64),
+    len: f64,
+    (x0, y0, x1, y1): (f64, f64, f64, f64),
+) -> bool {
+    let (mut t0, mut t1) = (0.0, len);
+    for (p, v, lo, hi) in [(x, vx, x0, x1), (y, vy, y0, y1)] {
+        if v.abs() < EPS {
+            if p < lo || p > hi {
+                return false;
+            }
+        } else {
+            let (a, b) = ((lo - p) / v, (hi - p) / v);
+            t0 = f64::max(t0, a.min(b));
+            t1 = f64::min(t1, a.max(b));
+            if t0 > t1 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Whether the center of a ball at `(x, y)` moving along `(vx, vy)` gets into
+/// `[x0, x1] × [y0, y1]` after moving more than 0 and at most `len`.
+///
+/// A ball already inside, or just leaving, does not get in again.
+fn enters_rect(
+    (x, y): (f64, f64),
+    (vx, vy): (f64, f64),
+    len: f64,
+    (x0, y0, x1, y1): (f64, f64, f64, f64),
+) -> bool {
+    let (mut t0, mut t1) = (f64::NEG_INFINITY, f64::INFINITY);
+    for (p, v, lo, hi) in [(x, vx, x0, x1), (y, vy, y0, y1)] {
+        if v.abs() < EPS {
+            if p < lo || p > hi {
+                return false;
+            }
+        } else {
+            let (a, b) = ((lo - p) / v, (hi - p) / v);
+            t0 = f64::max(t0, a.min(b));
+            t1 = f64::min(t1, a.max(b));
+        }
+    }
+    t0 < t1 && t0 > 0.0 && t0 <= len
+}
+
+fn cell_rect(i: usize, j: usize) -> (f64, f64, f64, f64) {
+    let (x0, y0) = (j as f64 * BLOCK_SIZE, i as f64 * BLOCK_SIZE);
+    (x0, y0, x0 + BLOCK_SIZE, y0 + BLOCK_SIZE)
+}
+
+impl GameState {
+    /// Points of the dashed line shown while aiming at `angle`, see
+    /// [`GameState::shoot`].
+    ///
+    /// Uses the same movement code as a real ball, but leaves the map as is.
+    pub fn aim_path(&mut self, angle: f64) -> Vec<(f64, f64)> {
+        let (vx, vy) = direction(angle);
+        let mut path = vec![];
+        let hh = self.mh as f64 * BLOCK_SIZE;
+        self.move_one_ball(
+            &mut BallStatus::launched(self.start_x, hh - BALL_R, vx, vy),
+            5000.0,
+            5,
+            Some(&mut path),
+        );
+        path
+    }
+
+    /// Cells the ball may touch while moving `len` from where it is.
+    fn cells_near(
+        &self,
+        ball: &BallStatus,
+        len: f64,
+    ) -> impl Iterator<Item = (usize, usize)> + 'static {
+        let (ex, ey) = (ball.x + ball.vx * len, ball.y + ball.vy * len);
+        let to_index = |v: f64, n: usize| (v.div_euclid(BLOCK_SIZE).max(0.0) as usize).min(n - 1);
+        let (i0, i1) = (
+            to_index(ball.y.min(ey) - BALL_R, self.mh),
+            to_index(ball.y.max(ey) + BALL_R, self.mh),
+        );
+        let (j0, j1) = (
+            to_index(ball.x.min(ex) - BALL_R, self.mw),
+            to_index(ball.x.max(ex) + BALL_R, self.mw),
+        );
+        (i0..=i1).flat_map(move |i| (j0..=j1).map(move |j| (i, j)))
+    }
+
+    fn first_contact(&self, ball: &BallStatus, len: f64) -> Option<Contact> {
+        let ww = self.mw as f64 * BLOCK_SIZE;
+        let hh = self.mh as f64 * BLOCK_SIZE;
+        let (vx, vy) = (ball.vx, ball.vy);
+
+        let mut best: Option<Contact> = None;
+        let mut consider = |t: f64, nx: f64, ny: f64, target: Target| {
+            if (-EPS..=len).contains(&t) && best.is_none_or(|c| t < c.t) {
+                best = Some(Contact {
+                    t: t.max(0.0),
+                    nx,
+                    ny,
+                    target,
+                });
+            }
+        };
+
+        if vx < 0.0 {
+            consider((ball.x - BALL_R) / -vx, 1.0, 0.0, Target::Wall);
+        } else if vx > 0.0 {
+            consider((ww - BALL_R - ball.x) / vx, -1.0, 0.0, Target::Wall);
+        }
+        if vy < 0.0 {
+            consider((ball.y - BALL_R) / -vy, 0.0, 1.0, Target::Wall);
+        } else if vy > 0.0 {
+            consider((hh - BALL_R - ball.y) / vy, 0.0, -1.0, Target::Floor);
+        }
+
+        for (i, j) in self.cells_near(ball, len) {
+            if !self.block_map[i][j].is_solid() {
+                continue;
+            }
+            let corners = self.block_shape(i, j).corners(cell_rect(i, j));
+            if let Some((t, nx, ny)) = sweep_polygon((ball.x, ball.y), (vx, vy), len, &corners) {
+                consider(t, nx, ny, Target::Block(i, j));
+            }
+        }
+
+        best
+    }
+
+    /// Takes the balls whose cell the center passes through in the next `len`.
+    fn pick_up(&mut self, ball: &BallStatus, len: f64) -> u32 {
+        let mut new_ball = 0;
+        for (i, j) in self.cells_near(ball, len) {
+            if self.block_map[i][j] == Cell::BallPickup
+                && crosses_rect((ball.x, ball.y), (ball.vx, ball.vy), len, cell_rect(i, j))
+            {
+                self.block_map[i][j] = Cell::Empty;
+                new_ball += 1;
+            }
+        }
+        new_ball
+    }
+
+    /// Uses the special cells the ball gets into in the next `len`, returns
+    /// whether it went through a random bounce.
+    fn use_specials(&mut self, ball: &BallStatus, len: f64) -> bool {
+        let mut bounce = false;
+        for (i, j) in self.cells_near(ball, len) {
+            let cell = self.block_map[i][j];
+            if !cell.is_trigger()
+                || !enters_rect((ball.x, ball.y), (ball.vx, ball.vy), len, cell_rect(i, j))
+            {
+                continue;
+            }
+            match cell {
+                Cell::Split => {
+                    // 分出来的球不再分
+                    if ball.is_temporary {
+                        continue;
+                    }
+                    let angle = ball.vy.atan2(ball.vx);
+                    for k in 0..SPLIT_BALLS {
+                        // 左右交替往外分
+                        let offset = (k / 2 + 1) as f64 * SPLIT_SPREAD;
+                        let angle = if k % 2 == 0 {
+                            angle + offset
+                        } else {
+                            angle - offset
+                        };
+                        let mut split =
+                            BallStatus::launched(ball.x, ball.y, angle.cos(), angle.sin());
+                        split.is_temporary = true;
+                        self.split_balls.push(split);
+                    }
+                }
+                Cell::Bounce => bounce = true,
+                Cell::Laser(laser) => {
+                    if laser != Laser::Column {
+                        for cell in self.block_map[i].iter_mut() {
+                            *cell = cell.on_hit();
+                        }
+                    }
+                    if laser != Laser::Row {
+                        for row in self.block_map.iter_mut() {
+                            row[j] = row[j].on_hit();
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if !self.used_cells.contains(&(i, j)) {
+                self.used_cells.push((i, j));
+            }
+        }
+        bounce
+    }
+
+    /// A direction for the next random bounce, never too flat.
+    fn random_direction(&mut self) -> (f64, f64) {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(BOUNCE_STREAM | self.n_bounces);
+        self.n_bounces += 1;
+        let angle = rng.gen_range(0.2..PI - 0.2) + if rng.gen_bool(0.5) { PI } else { 0.0 };
+        (angle.cos(), angle.sin())
+    }
+
+    /// Moves `ball` forward by `len`, bouncing on walls and blocks, and returns
+    /// the number of balls picked up on the way.
+    ///
+    /// With `path` the move is only a preview: every visited point is pushed
+    /// to it and the map is not touched.
+    pub(crate) fn move_one_ball(
+        &mut self,
+        ball: &mut BallStatus,
+        len: f64,
+        max_bounces: u32,
+        mut path: Option<&mut Vec<(f64, f64)>>,
+    ) -> u32 {
+        let is_aimline = path.is_some();
+        let mut rest = len;
+        let mut rest_bounces = max_bounces;
+        let mut new_ball = 0;
+
+        if let Some(path) = path.as_mut() {
+            path.push((ball.x, ball.y));
+        }
+
+        while rest > EPS && rest_bounces > 0 {
+            let contact = self.first_contact(ball, rest);
+            let t = contact.map_or(rest, |c| c.t);
+
+            let mut bounce = false;
+            if !is_aimline {
+                new_ball += self.pick_up(ball, t);
+                bounce = self.use_specials(ball, t);
+            }
+            ball.x += ball.vx * t;
+            ball.y += ball.vy * t;
+            rest -= t;
+            if bounce {
+                (ball.vx, ball.vy) = self.random_direction();
+            }
+
+            if let Some(path) = path.as_mut() {
+                path.push((ball.x, ball.y));
+            }
+
+            let Some(contact) = contact else { break };
+            match contact.target {
+                Target::Floor => {
+                    if ball.is_temporary {
+                        ball.moving_status = BallMovingStatus::Gone;
+                    } else if self.new_start_x.is_some() {
+                        ball.moving_status = BallMovingStatus::Backing;
+                    } else {
+                        if !is_aimline {
+                            self.new_start_x = Some(ball.x);
+                        }
+                        ball.moving_status = BallMovingStatus::Done;
+                    }
+                    break;
+                }
+                Target::Block(i, j) => {
+                    if !is_aimline {
+                        self.block_map[i][j] = self.block_map[i][j].on_hit();
+                    }
+                }
+                Target::Wall => {}
+            }
+
+            // 随机弹开后可能已经在离开了
+            let dot = f64::min(ball.vx * contact.nx + ball.vy * contact.ny, 0.0);
+            ball.vx -= 2.0 * dot * contact.nx;
+            ball.vy -= 2.0 * dot * contact.ny;
+            if ball.vy.abs() < MIN_VY {
+                ball.vy = MIN_VY.copysign(ball.vy);
+                ball.vx = (1.0 - MIN_VY * MIN_VY).sqrt().copysign(ball.vx);
+            }
+            rest_bounces -= 1;
+        }
+
+        new_ball
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BlockShape;
+
+    const CLOSE: f64 = 1e-9;
+
+    /// An empty board with a block of `hp` at every `(i, j)` of `blocks`.
+    fn board(blocks: &[(usize, usize)], hp: i32) -> GameState {
+        let mut state = GameState::new(6, 8, 1);
+        for row in state.block_map.iter_mut() {
+            row.fill(Cell::Empty);
+        }
+        for &(i, j) in blocks {
+            state.block_map[i][j] = Cell::Block { hp };
+        }
+        state
+    }
+
+    fn unit(vx: f64, vy: f64) -> (f64, f64) {
+        (vx / vx.hypot(vy), vy / vx.hypot(vy))
+    }
+
+    #[test]
+    fn corner_contact() {
+        let corners = BlockShape::Square.corners(cell_rect(2, 2));
+        let (vx, vy) = unit(-1.0, -1.0);
+        let start = (300.0 - vx * 100.0, 300.0 - vy * 100.0);
+        let (t, nx, ny) = sweep_polygon(start, (vx, vy), 200.0, &corners).unwrap();
+        assert!((t - (100.0 - BALL_R)).abs() < CLOSE);
+        assert!((nx - -vx).abs() < CLOSE && (ny - -vy).abs() < CLOSE);
+    }
+
+    #[test]
+    fn corner_hit() {
+        let mut state = board(&[(2, 2)], 5);
+        let (vx, vy) = unit(-1.0, -1.0);
+        let mut ball = BallStatus::launched(300.0 - vx * 100.0, 300.0 - vy * 100.0, vx, vy);
+        state.move_one_ball(&mut ball, 200.0, 1, None);
+        // 只弹一次，停在离角正好一个半径的地方，原路弹回
+        assert!(((ball.x - 300.0).hypot(ball.y - 300.0) - BALL_R).abs() < CLOSE);
+        assert!((ball.vx - -vx).abs() < CLOSE && (ball.vy - -vy).abs() < CLOSE);
+        assert_eq!(state.block_map[2][2], Cell::Block { hp: 4 });
+    }
+
+    #[test]
+    fn seam_is_flat() {
+        let mut state = board(&[(2, 1), (2, 2)], 5);
+        let (vx, vy) = unit(0.3, -1.0);
+        let contact = (200.0, 300.0 + BALL_R);
+        let mut ball = BallStatus::launched(contact.0 - vx * 100.0, contact.1 - vy * 100.0, vx, vy);
+        state.move_one_ball(&mut ball, 200.0, 1, None);
+        assert!((ball.x - contact.0).abs() < CLOSE && (ball.y - contact.1).abs() < CLOSE);
+        assert!((ball.vx - vx).abs() < CLOSE && (ball.vy - -vy).abs() < CLOSE);
+    }
+
+    #[test]
+    fn flat_side_straight_back() {
+        let mut state = board(&[(2, 2)], 5);
+        let mut ball = BallStatus::launched(250.0, 500.0, 0.0, -1.0);
+        state.move_one_ball(&mut ball, 300.0, 1, None);
+        assert!((ball.y - (300.0 + BALL_R)).abs() < CLOSE);
+        assert_eq!((ball.vx, ball.vy), (0.0, 1.0));
+        assert_eq!(state.block_map[2][2], Cell::Block { hp: 4 });
+    }
+
+    #[test]
+    fn slope_bounce_comes_down() {
+        // 竖直打在斜边上会水平弹开，不能一直横着走
+        let layout =
+            "size 4x6 / start 250 / . . 1tl . / . . . . / . . . . / . . . . / . . . . / . . . ."
+                .parse()
+                .unwrap();
+        let mut state = GameState::from_layout(layout, 1, crate::Rules::classic());
+        assert!(state.shoot(PI / 2.0));
+        for _ in 0..100_000 {
+            if !state.is_moving() {
+                return;
+            }
+            state.step();
+        }
+        panic!("the turn never ended");
+    }
+}
@@ -0,0 +1,261 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{GameState, Layout, ParseLayoutError, Rules};
+
+/// A whole game: the seed, the map size, the rules, every shot and every
+/// recall.
+///
+/// Written as
+/// `seed:mwxmh:special_chance:special_weights:obstacle_level:difficulty:shots`
+/// with the shots as `vx,vy;vx,vy@step;...`, see [`Rules`] for the fields
+/// between. The numbers use the shortest decimal form that parses back to
+/// the same `f64`, so playing a parsed replay gives exactly the recorded
+/// game. A shot recalled after `step` steps ends with `@step`,
+/// `obstacle_level` is empty without obstacles. A game started from a
+/// [`Layout`] ends with `|` and the layout, its lines separated by `/`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub mw: usize,
+    pub mh: usize,
+    pub shots: Vec<(f64, f64)>,
+    /// see [`GameState::recalls`]
+    pub recalls: Vec<(usize, u32)>,
+    pub rules: Rules,
+    /// see [`GameState::layout`]
+    pub layout: Option<Layout>,
+}
+
+impl Replay {
+    /// The game after its first `n_shots` shots have been played out, so at
+    /// level `n_shots + 1` unless it was over earlier.
+    pub fn state_after(&self, n_shots: usize) -> GameState {
+        let mut state = match &self.layout {
+            Some(layout) => GameState::from_layout(layout.clone(), self.seed, self.rules),
+            None => GameState::with_rules(self.mw, self.mh, self.seed, self.rules),
+        };
+        for i in 0..n_shots.min(self.shots.len()) {
+            if !self.launch(i, &mut state) {
+                break;
+            }
+            state.finish_turn();
+        }
+        state
+    }
+
+    /// Launches shot `i` in `state`, set up to be recalled as recorded.
+    pub fn launch(&self, i: usize, state: &mut GameState) -> bool {
+        let Some(&(vx, vy)) = self.shots.get(i) else {
+            return false;
+        };
+        if !state.launch(vx, vy) {
+            return false;
+        }
+        if let Some(&(_, at)) = self.recalls.iter().find(|(shot, _)| *shot == i) {
+            state.recall_after(at);
+        }
+        true
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}x{}:{}:{}:{}:{}:",
+            self.seed,
+            self.mw,
+            self.mh,
+            self.rules.special_chance,
+            self.rules.special_weights,
+            self.rules
+                .obstacle_level
+                .map_or(String::new(), |level| level.to_string()),
+            self.rules.difficulty,
+        )?;
+        for (i, (vx, vy)) in self.shots.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            write!(f, "{vx},{vy}")?;
+            if let Some((_, at)) = self.recalls.iter().find(|(shot, _)| *shot == i) {
+                write!(f, "@{at}")?;
+            }
+        }
+        if let Some(layout) = &self.layout {
+            write!(f, "|{}", layout.to_string().trim_end().replace('\n', "/"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseReplayError {
+    /// seed, map size or rules are missing or invalid
+    Header,
+    /// the shot with this index is not two numbers pointing up, optionally
+    /// followed by `@step`
+    Shot(usize),
+    /// the layout after `|` is invalid or not of the map size
+    Layout(Option<ParseLayoutError>),
+}
+
+impl fmt::Display for ParseReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header => {
+                f.write_str("invalid replay header, expected `seed:mwxmh:` and the rules")
+            }
+            Self::Shot(i) => write!(f, "invalid shot #{}", i + 1),
+            Self::Layout(Some(err)) => write!(f, "invalid layout: {err}"),
+            Self::Layout(None) => f.write_str("the layout is not of the map size"),
+        }
+    }
+}
+
+impl Error for ParseReplayError {}
+
+impl FromStr for Replay {
+    type Err = ParseReplayError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, layout) = match s.trim().split_once('|') {
+            Some((s, layout)) => (
+                s,
+                Some(
+                    layout
+                        .parse::<Layout>()
+                        .map_err(|err| ParseReplayError::Layout(Some(err)))?,
+                ),
+            ),
+            None => (s.trim(), None),
+        };
+        let [seed, size, special_chance, special_weights, obstacle_level, difficulty, shots] =
+            s.splitn(7, ':').collect::<Vec<_>>()[..]
+        else {
+            return Err(ParseReplayError::Header);
+        };
+        let special_weights = special_weights
+            .parse()
+            .map_err(|_| ParseReplayError::Header)?;
+        let special_chance = special_chance
+            .parse()
+            .ok()
+            .filter(|p: &f64| (0.0..=1.0).contains(p))
+            .ok_or(ParseReplayError::Header)?;
+        let obstacle_level = match obstacle_level {
+            "" => None,
+            level => Some(level.parse().map_err(|_| ParseReplayError::Header)?),
+        };
+        let difficulty = difficulty.parse().map_err(|_| ParseReplayError::Header)?;
+        let seed = seed.parse().map_err(|_| ParseReplayError::Header)?;
+        let (mw, mh) = size
+            .split_once('x')
+            .and_then(|(mw, mh)| Some((mw.parse().ok()?, mh.parse().ok()?)))
+            .filter(|&(mw, mh): &(usize, usize)| mw >= 4 && mh >= 4)
+            .ok_or(ParseReplayError::Header)?;
+        if layout
+            .as_ref()
+            .is_some_and(|layout| (layout.mw, layout.mh) != (mw, mh))
+        {
+            return Err(ParseReplayError::Layout(None));
+        }
+
+        let mut recalls = vec![];
+        let shots = shots
+            .split(';')
+            .filter(|shot| !shot.is_empty())
+            .enumerate()
+            .map(|(i, shot)| {
+                let (shot, recall) = match shot.split_once('@') {
+                    Some((shot, at)) => (shot, Some(at)),
+                    None => (shot, None),
+                };
+                if let Some(at) = recall {
+                    recalls.push((i, at.parse().map_err(|_| ParseReplayError::Shot(i))?));
+                }
+                shot.split_once(',')
+                    .and_then(|(vx, vy)| Some((vx.parse().ok()?, vy.parse().ok()?)))
+                    .filter(|&(vx, vy): &(f64, f64)| vx.is_finite() && vy.is_finite() && vy < 0.0)
+                    .ok_or(ParseReplayError::Shot(i))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            seed,
+            mw,
+            mh,
+            shots,
+            recalls,
+            rules: Rules {
+                special_chance,
+                special_weights,
+                obstacle_level,
+                difficulty,
+            },
+            layout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DifficultyProfile, SpecialWeights};
+
+    /// A game from a layout under other rules, with a turn recalled halfway.
+    fn recorded() -> GameState {
+        let layout = "size 6x8 / balls 2 / start 150 / . 5 . 3tr . . / . . o . 4 . \
+            / . . . . . . / . . . . . . / . . . . . . / . . . . . . / . . . . . . \
+            / . . . . . . / next / 6 . . 6 . 6"
+            .parse()
+            .unwrap();
+        let rules = Rules {
+            special_chance: 0.4,
+            special_weights: SpecialWeights {
+                laser: 2,
+                split: 0,
+                bounce: 3,
+            },
+            obstacle_level: Some(3),
+            difficulty: DifficultyProfile::HARD,
+        };
+        let mut state = GameState::from_layout(layout, 5, rules);
+        for k in 0..12 {
+            if !state.shoot(0.4 + (k as f64 * 0.37) % 2.3) {
+                break;
+            }
+            if k == 4 {
+                for _ in 0..30 {
+                    state.step();
+                }
+                state.recall();
+            }
+            state.finish_turn();
+        }
+        state
+    }
+
+    #[test]
+    fn round_trip() {
+        let replay = recorded().replay();
+        assert!(!replay.recalls.is_empty() && replay.layout.is_some());
+        assert_eq!(replay.to_string().parse::<Replay>().unwrap(), replay);
+    }
+
+    #[test]
+    fn state_after_is_the_live_game() {
+        let live = recorded();
+        let replay: Replay = live.replay().to_string().parse().unwrap();
+        let replayed = replay.state_after(replay.shots.len());
+        assert_eq!(replayed.block_map, live.block_map);
+        assert_eq!(replayed.block_shapes, live.block_shapes);
+        assert_eq!(
+            (replayed.level, replayed.n_balls, replayed.start_x),
+            (live.level, live.n_balls, live.start_x)
+        );
+        assert_eq!(replayed.is_game_over, live.is_game_over);
+    }
+}
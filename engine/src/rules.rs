@@ -0,0 +1,111 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Cell, DifficultyProfile, Laser};
+
+/// default for [`Rules::special_chance`]
+pub const SPECIAL_CHANCE: f64 = 0.15;
+/// default for [`Rules::obstacle_level`]
+pub const OBSTACLE_LEVEL: u32 = 20;
+
+/// How often each kind of special cell comes, against the others: each of
+/// the three lasers, a split and a random bounce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpecialWeights {
+    pub laser: u32,
+    pub split: u32,
+    pub bounce: u32,
+}
+
+impl SpecialWeights {
+    /// Every kind as likely, how specials came before they could be weighed.
+    pub const EVEN: Self = Self {
+        laser: 1,
+        split: 1,
+        bounce: 1,
+    };
+
+    /// Special cells to pick one from, each as many times as its weight.
+    pub(crate) fn cells(&self) -> Vec<Cell> {
+        let weighed = [
+            (Cell::Laser(Laser::Row), self.laser),
+            (Cell::Laser(Laser::Column), self.laser),
+            (Cell::Laser(Laser::Cross), self.laser),
+            (Cell::Split, self.split),
+            (Cell::Bounce, self.bounce),
+        ];
+        weighed
+            .into_iter()
+            .flat_map(|(cell, weight)| std::iter::repeat_n(cell, weight as usize))
+            .collect()
+    }
+}
+
+impl Default for SpecialWeights {
+    fn default() -> Self {
+        Self::EVEN
+    }
+}
+
+/// Written as `laser,split,bounce`.
+impl fmt::Display for SpecialWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{}", self.laser, self.split, self.bounce)
+    }
+}
+
+impl FromStr for SpecialWeights {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [laser, split, bounce] = s.split(',').collect::<Vec<_>>()[..] else {
+            return Err(());
+        };
+        Ok(Self {
+            laser: laser.parse().map_err(|_| ())?,
+            split: split.parse().map_err(|_| ())?,
+            bounce: bounce.parse().map_err(|_| ())?,
+        })
+    }
+}
+
+/// What new rows may hold. A seed gives other boards under other rules, so
+/// they are part of a [`Replay`](crate::Replay).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rules {
+    /// chance for a new row to have a special cell: a laser, a split or a
+    /// random bounce
+    pub special_chance: f64,
+    /// which special cell it is
+    pub special_weights: SpecialWeights,
+    /// rows from this level on may have triangles, armored and steel blocks,
+    /// never if `None`
+    pub obstacle_level: Option<u32>,
+    /// how many blocks and balls new rows have, and their hp
+    pub difficulty: DifficultyProfile,
+}
+
+impl Rules {
+    /// Only square blocks and balls to get, how the game was first played.
+    pub fn classic() -> Self {
+        Self {
+            special_chance: 0.0,
+            special_weights: SpecialWeights::EVEN,
+            obstacle_level: None,
+            difficulty: DifficultyProfile::NORMAL,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            special_chance: SPECIAL_CHANCE,
+            special_weights: SpecialWeights::EVEN,
+            obstacle_level: Some(OBSTACLE_LEVEL),
+            difficulty: DifficultyProfile::NORMAL,
+        }
+    }
+}
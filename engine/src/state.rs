@@ -0,0 +1,580 @@
+use std::collections::VecDeque;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BallMovingStatus, BallStatus, BlockShape, Cell, GameMode, Goal, Layout, Replay, Rules, BALL_R,
+    BLOCK_SIZE, EPS, NEXT_BALL_TIME_DIST, STEP_DIST,
+};
+
+/// chances for a block in a new row to be steel, armored or a triangle, once
+/// obstacles are allowed
+const STEEL_CHANCE: f64 = 0.05;
+const ARMORED_CHANCE: f64 = 0.1;
+const TRIANGLE_CHANCE: f64 = 0.2;
+
+/// Map size of daily games, the same for everyone.
+pub const DAILY_SIZE: (usize, usize) = (10, 16);
+
+/// Seed of the daily game on `date`, `YYYY-MM-DD` in UTC.
+pub fn daily_seed(date: &str) -> u64 {
+    // FNV-1a，不能用每次运行都不一样的 hasher
+    date.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Unit vector of a shot at `angle`, see [`GameState::shoot`].
+pub(crate) fn direction(angle: f64) -> (f64, f64) {
+    (angle.cos(), -angle.sin())
+}
+
+/// What happened during one [`GameState::step`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// balls picked up in this step
+    pub new_balls: u32,
+    /// all balls are back, the level went up and a new row was pushed
+    pub turn_done: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameState {
+    /// map width, number of blocks
+    pub mw: usize,
+    /// map height, number of blocks
+    pub mh: usize,
+    /// rows from top to bottom
+    pub block_map: VecDeque<Vec<Cell>>,
+    /// shape of every block in `block_map`, see [`GameState::block_shape`]
+    #[serde(default)]
+    pub block_shapes: VecDeque<Vec<BlockShape>>,
+    pub moving_balls: Vec<BallStatus>,
+    pub n_waiting_balls: u32,
+    pub n_balls: u32,
+    pub level: u32,
+    pub start_x: f64,
+    pub is_game_over: bool,
+    /// the game ended with its [`GameState::goal`] reached
+    #[serde(default)]
+    pub is_cleared: bool,
+    /// every row is generated from this, see [`GameState::new`]
+    pub seed: u64,
+    #[serde(default)]
+    pub mode: GameMode,
+    /// `YYYY-MM-DD` of a [`GameMode::Daily`] game, the day it is the game of
+    pub daily_date: Option<String>,
+    pub rules: Rules,
+    /// the board the game started from, `None` if it started from random rows
+    #[serde(default)]
+    pub layout: Option<Layout>,
+    /// every `(vx, vy)` passed to [`GameState::launch`] so far
+    pub shots: Vec<(f64, f64)>,
+    /// `(shot, step)` of every turn ended early by [`GameState::recall`]:
+    /// the index in `shots` and the number of steps played before
+    #[serde(default)]
+    pub recalls: Vec<(usize, u32)>,
+    /// shots taken back with [`GameState::undo`]
+    #[serde(default)]
+    pub n_undos: u32,
+    pub(crate) new_start_x: Option<f64>,
+    /// special cells used in the running turn, removed when it ends
+    pub(crate) used_cells: Vec<(usize, usize)>,
+    /// balls made by splits in the running step
+    #[serde(skip)]
+    pub(crate) split_balls: Vec<BallStatus>,
+    /// random bounces so far, each one draws from its own stream
+    pub(crate) n_bounces: u64,
+    is_moving: bool,
+    /// steps played in the running turn
+    #[serde(default)]
+    turn_steps: u32,
+    #[serde(default)]
+    recall_at: Option<u32>,
+    waiting_next: u32,
+}
+
+impl GameState {
+    /// Starts a new game. Rows only depend on `seed` and their rank, so the
+    /// same seed and the same shots always give the same board.
+    pub fn new(mw: usize, mh: usize, seed: u64) -> Self {
+        Self::with_rules(mw, mh, seed, Rules::default())
+    }
+
+    /// Starts a new game where new rows follow `rules`.
+    pub fn with_rules(mw: usize, mh: usize, seed: u64, rules: Rules) -> Self {
+        let mut state = Self {
+            mw,
+            mh,
+            block_map: vec![vec![Cell::Empty; mw]; mh].into(),
+            block_shapes: vec![vec![BlockShape::Square; mw]; mh].into(),
+            moving_balls: vec![],
+            n_waiting_balls: 0,
+            n_balls: 1,
+            level: 1,
+            start_x: mw as f64 * BLOCK_SIZE / 2.0,
+            is_game_over: false,
+            is_cleared: false,
+            seed,
+            mode: GameMode::Endless,
+            daily_date: None,
+            rules: Rules {
+                special_chance: rules.special_chance.clamp(0.0, 1.0),
+                difficulty: rules.difficulty.clamped(),
+                ..rules
+            },
+            layout: None,
+            shots: vec![],
+            recalls: vec![],
+            n_undos: 0,
+            new_start_x: None,
+            used_cells: vec![],
+            split_balls: vec![],
+            n_bounces: 0,
+            is_moving: false,
+            turn_steps: 0,
+            recall_at: None,
+            waiting_next: 0,
+        };
+        for i in 0..mh / 2 {
+            state.update_blocks_and_check_game_over(i + 1);
+        }
+        state
+    }
+
+    /// Whether shots can be taken back: not in levels or the daily game,
+    /// where stars, progress and results are kept.
+    pub fn allows_undo(&self) -> bool {
+        self.layout.is_none() && self.mode != GameMode::Daily
+    }
+
+    /// Takes back the last shot: goes back to `before`, the game as it was
+    /// when the shot was launched, and counts it in `n_undos`.
+    pub fn undo(&mut self, before: GameState) {
+        let n_undos = self.n_undos + 1;
+        *self = before;
+        self.n_undos = n_undos;
+    }
+
+    /// The daily game on `date`, see [`daily_seed`]: the same map size, rules
+    /// and rows for everyone on that day.
+    pub fn daily(date: &str) -> Self {
+        let (mw, mh) = DAILY_SIZE;
+        let mut state = Self::with_rules(mw, mh, daily_seed(date), Rules::default());
+        state.mode = GameMode::Daily;
+        state.daily_date = Some(date.to_string());
+        state
+    }
+
+    /// Starts a new game on `layout`. After its scripted rows, new rows come
+    /// from `rules` and the layout's seed, or `seed` if it has none.
+    pub fn from_layout(layout: Layout, seed: u64, rules: Rules) -> Self {
+        let seed = layout.seed.unwrap_or(seed);
+        let mut state = Self::with_rules(layout.mw, layout.mh, seed, rules);
+        state.block_map = layout.cells.iter().cloned().collect();
+        state.block_shapes = layout.shapes.iter().cloned().collect();
+        state
+            .block_map
+            .resize(layout.mh, vec![Cell::Empty; layout.mw]);
+        state.n_balls = layout.n_balls.max(1);
+        state.start_x = layout
+            .start_x
+            .clamp(BALL_R, layout.mw as f64 * BLOCK_SIZE - BALL_R);
+        if layout.max_shots.is_some() {
+            state.mode = GameMode::Puzzle;
+        }
+        state.layout = Some(layout);
+        state
+    }
+
+    /// Shape of the block at row `i`, column `j`.
+    pub fn block_shape(&self, i: usize, j: usize) -> BlockShape {
+        // 以前存的游戏没有形状
+        self.block_shapes
+            .get(i)
+            .and_then(|row| row.get(j))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// What the game is won by, `None` for endless games.
+    pub fn goal(&self) -> Option<Goal> {
+        let layout = self.layout.as_ref()?;
+        match self.mode {
+            GameMode::Puzzle => Some(layout.goal.unwrap_or(Goal::Clear)),
+            _ => layout.goal,
+        }
+    }
+
+    fn is_goal_reached(&self) -> bool {
+        match self.goal() {
+            None => false,
+            Some(Goal::Clear) => !self.block_map.iter().flatten().any(|cell| cell.is_lethal()),
+            Some(Goal::Survive(turns)) => self.shots.len() as u32 >= turns,
+            Some(Goal::Collect(balls)) => self.n_balls >= balls,
+        }
+    }
+
+    /// Shots a puzzle has to be cleared in.
+    pub fn max_shots(&self) -> Option<u32> {
+        self.layout.as_ref()?.max_shots
+    }
+
+    /// Stars for a cleared puzzle: three within par, two within half way
+    /// from par to the limit, one otherwise, and none if not cleared.
+    pub fn stars(&self) -> u32 {
+        let Some(max_shots) = self.max_shots().filter(|_| self.is_cleared) else {
+            return 0;
+        };
+        let par = self
+            .layout
+            .as_ref()
+            .and_then(|layout| layout.par)
+            .unwrap_or(max_shots.div_ceil(2));
+        let used = self.shots.len() as u32;
+        if used <= par {
+            3
+        } else if used <= (par + max_shots).div_ceil(2) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether balls of the last shot are still on the way.
+    pub fn is_moving(&self) -> bool {
+        self.is_moving
+    }
+
+    /// Shoots all balls towards `angle`, in radians counter-clockwise from the
+    /// positive x axis, so straight up is `PI / 2`.
+    pub fn shoot(&mut self, angle: f64) -> bool {
+        let (vx, vy) = direction(angle);
+        self.launch(vx, vy)
+    }
+
+    /// Shoots all balls along `(vx, vy)`, in canvas coordinates where y grows
+    /// downwards. The direction is made a unit vector first, and recorded so.
+    ///
+    /// Returns `false` without doing anything if a turn is still running, the
+    /// game is over or the direction does not point up.
+    pub fn launch(&mut self, vx: f64, vy: f64) -> bool {
+        let len = vx.hypot(vy);
+        if self.is_moving || self.is_game_over || vy >= 0.0 || !(len > EPS && len.is_finite()) {
+            return false;
+        }
+        // 已经是单位向量的不再除，回放时才和原来的一样
+        let (vx, vy) = if (len - 1.0).abs() > EPS {
+            (vx / len, vy / len)
+        } else {
+            (vx, vy)
+        };
+        self.shots.push((vx, vy));
+        self.moving_balls = vec![];
+        self.n_waiting_balls = self.n_balls;
+        self.waiting_next = 0;
+        self.is_moving = true;
+        self.turn_steps = 0;
+        self.recall_at = None;
+        true
+    }
+
+    /// Ends the running turn at once: balls still on the way are called back
+    /// to where the first one landed, or to where they were shot from if none
+    /// has landed yet, and the ones not shot yet stay there.
+    pub fn recall(&mut self) -> StepOutcome {
+        if !self.is_moving {
+            return StepOutcome::default();
+        }
+        self.recalls.push((self.shots.len() - 1, self.turn_steps));
+        self.moving_balls.clear();
+        self.n_waiting_balls = 0;
+        self.end_turn();
+        StepOutcome {
+            new_balls: 0,
+            turn_done: true,
+        }
+    }
+
+    /// Recalls the running turn once it has played `at` steps, to replay a
+    /// recorded [`GameState::recall`].
+    pub fn recall_after(&mut self, at: u32) {
+        if self.is_moving {
+            self.recall_at = Some(at);
+        }
+    }
+
+    /// Advances the running turn by one step, in which a running ball travels
+    /// [`STEP_DIST`].
+    ///
+    /// The step length is fixed so that a game only depends on its seed and
+    /// shots; play it faster or slower by calling this more or less often.
+    pub fn step(&mut self) -> StepOutcome {
+        if !self.is_moving {
+            return StepOutcome::default();
+        }
+        if self.recall_at == Some(self.turn_steps) {
+            return self.recall();
+        }
+
+        self.turn_steps += 1;
+        let (new_balls, turn_done) = self.simulate_moving(STEP_DIST);
+        self.n_balls += new_balls;
+        if turn_done {
+            self.end_turn();
+        }
+
+        StepOutcome {
+            new_balls,
+            turn_done,
+        }
+    }
+
+    /// Steps until the running turn is over.
+    pub fn finish_turn(&mut self) -> StepOutcome {
+        let mut outcome = StepOutcome::default();
+        while self.is_moving {
+            let step = self.step();
+            outcome.new_balls += step.new_balls;
+            outcome.turn_done = step.turn_done;
+        }
+        outcome
+    }
+
+    /// Everything needed to play this game again from the start.
+    pub fn replay(&self) -> Replay {
+        Replay {
+            seed: self.seed,
+            mw: self.mw,
+            mh: self.mh,
+            shots: self.shots.clone(),
+            recalls: self.recalls.clone(),
+            rules: self.rules,
+            layout: self.layout.clone(),
+        }
+    }
+
+    fn end_turn(&mut self) {
+        self.start_x = self.new_start_x.take().unwrap_or(self.start_x);
+        self.is_moving = false;
+        self.recall_at = None;
+        for (i, j) in std::mem::take(&mut self.used_cells) {
+            self.block_map[i][j] = Cell::Empty;
+        }
+        self.level += 1;
+        // 新的一排下来之前算
+        if self.is_goal_reached() {
+            self.is_cleared = true;
+            self.is_game_over = true;
+            return;
+        }
+        // 谜题不加新的一排
+        if self.mode == GameMode::Puzzle {
+            self.is_game_over = self
+                .max_shots()
+                .is_some_and(|max_shots| self.shots.len() as u32 >= max_shots);
+            return;
+        }
+        let n_rank = self.mh / 2 + self.level as usize - 1;
+        self.is_game_over = self.update_blocks_and_check_game_over(n_rank);
+    }
+
+    fn update_blocks_and_check_game_over(&mut self, n_rank: usize) -> bool {
+        self.block_shapes
+            .resize(self.block_map.len(), vec![BlockShape::Square; self.mw]);
+        if self.block_map.pop_back().is_none() {
+            return false;
+        }
+        self.block_shapes.pop_back();
+
+        let (new_line, new_shapes) = self
+            .scripted_row()
+            .unwrap_or_else(|| self.random_row(n_rank));
+        self.block_map.push_front(new_line);
+        self.block_shapes.push_front(new_shapes);
+
+        // 钢块之类的到底就拆掉，不算输
+        let bottom = self.block_map.back_mut().unwrap();
+        for cell in bottom.iter_mut() {
+            if cell.is_solid() && !cell.is_lethal() {
+                *cell = Cell::Empty;
+            }
+        }
+        bottom.iter().any(|cell| cell.is_lethal())
+    }
+
+    /// The row of the layout for this turn, if it still has one.
+    fn scripted_row(&self) -> Option<(Vec<Cell>, Vec<BlockShape>)> {
+        let layout = self.layout.as_ref()?;
+        let i = (self.level as usize).checked_sub(2)?;
+        let row = layout.next_rows.get(i)?.clone();
+        let shapes = layout.next_shapes.get(i).cloned().unwrap_or_default();
+        Some((row, shapes))
+    }
+
+    fn random_row(&self, n_rank: usize) -> (Vec<Cell>, Vec<BlockShape>) {
+        // 每一排用独立的 stream，和之前生成过多少排无关
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(n_rank as u64);
+        let difficulty = &self.rules.difficulty;
+        let min_n = (n_rank / difficulty.min_blocks_every as usize + 1).min(self.mw / 3);
+        let max_n = (n_rank / difficulty.max_blocks_every as usize + n_rank.min(3) + 2)
+            .min(self.mw - 2)
+            .max(min_n + 1);
+        let n = rng.gen_range(min_n..max_n);
+        let hp = difficulty.row_hp(n_rank);
+        let mut new_line: Vec<Cell> = (0..self.mw)
+            .map(|idx| {
+                if idx < n {
+                    Cell::Block { hp }
+                } else {
+                    Cell::Empty
+                }
+            })
+            .collect();
+        if rng.gen_bool(difficulty.pickup_chance) {
+            new_line[n] = Cell::BallPickup;
+        }
+        new_line.shuffle(&mut rng);
+        if self.rules.special_chance > 0.0 && rng.gen_bool(self.rules.special_chance) {
+            let empty: Vec<usize> = (0..self.mw)
+                .filter(|&j| new_line[j] == Cell::Empty)
+                .collect();
+            if let Some(&j) = empty.choose(&mut rng) {
+                // 权重都是 0 就不放
+                if let Some(&cell) = self.rules.special_weights.cells().choose(&mut rng) {
+                    new_line[j] = cell;
+                }
+            }
+        }
+        let mut new_shapes = vec![BlockShape::Square; self.mw];
+        if self
+            .rules
+            .obstacle_level
+            .is_some_and(|level| self.level >= level)
+        {
+            for (shape, cell) in new_shapes.iter_mut().zip(new_line.iter_mut()) {
+                if !cell.is_lethal() {
+                    continue;
+                }
+                let r: f64 = rng.gen();
+                if r < STEEL_CHANCE {
+                    *cell = Cell::Steel;
+                } else if r < STEEL_CHANCE + ARMORED_CHANCE {
+                    *cell = Cell::Armored { hp, cracked: false };
+                } else if r < STEEL_CHANCE + ARMORED_CHANCE + TRIANGLE_CHANCE {
+                    *shape = *BlockShape::TRIANGLES.choose(&mut rng).unwrap();
+                }
+            }
+        }
+        (new_line, new_shapes)
+    }
+
+    fn simulate_moving(&mut self, v: f64) -> (u32, bool) {
+        let hh = self.mh as f64 * BLOCK_SIZE;
+
+        let mut new_ball = 0;
+
+        let mut balls = std::mem::take(&mut self.moving_balls);
+        for ball in balls.iter_mut() {
+            match ball.moving_status {
+                BallMovingStatus::Done | BallMovingStatus::Gone => {}
+                BallMovingStatus::Backing => {
+                    let new_start_x = self.new_start_x.unwrap();
+                    let rest_lx = new_start_x - ball.x;
+                    if rest_lx.abs() < EPS {
+                        ball.moving_status = BallMovingStatus::Done;
+                    } else {
+                        ball.x += rest_lx.abs().min(v.abs() * 0.678).copysign(rest_lx);
+                    }
+                }
+                BallMovingStatus::Running => {
+                    new_ball += self.move_one_ball(ball, v, 1000, None);
+                }
+            }
+        }
+        self.moving_balls = balls;
+        self.moving_balls.append(&mut self.split_balls);
+
+        if self.n_waiting_balls > 0 {
+            if self.waiting_next == 0 {
+                // 还没发的球都沿这一轮瞄的方向出去，之后各走各的
+                let (vx, vy) = *self.shots.last().unwrap();
+                let go_more = self.moving_balls.len() as f64
+                    / (self.n_waiting_balls as f64 + self.moving_balls.len() as f64)
+                    / 10.0;
+                self.moving_balls.push(BallStatus::launched(
+                    self.start_x + vx * NEXT_BALL_TIME_DIST * go_more,
+                    hh - BALL_R + vy * NEXT_BALL_TIME_DIST * go_more,
+                    vx,
+                    vy,
+                ));
+                self.n_waiting_balls -= 1;
+                self.waiting_next = (NEXT_BALL_TIME_DIST / v) as u32;
+            } else {
+                self.waiting_next -= 1;
+            }
+        }
+
+        let done = self.n_waiting_balls == 0
+            && self.moving_balls.iter().all(|ball| {
+                matches!(
+                    ball.moving_status,
+                    BallMovingStatus::Done | BallMovingStatus::Gone
+                )
+            });
+
+        (new_ball, done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `n_shots` shots fanned across the board, fewer if the game ends.
+    fn play(state: &mut GameState, n_shots: usize) {
+        for k in 0..n_shots {
+            if !state.shoot(0.3 + (k as f64 * 0.41) % 2.5) {
+                break;
+            }
+            state.finish_turn();
+        }
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let rules = Rules {
+            obstacle_level: Some(2),
+            ..Rules::default()
+        };
+        let mut a = GameState::with_rules(10, 16, 42, rules);
+        let mut b = GameState::with_rules(10, 16, 42, rules);
+        play(&mut a, 30);
+        play(&mut b, 30);
+        assert!(a.level > 2);
+        assert_eq!(a.block_map, b.block_map);
+        assert_eq!(a.block_shapes, b.block_shapes);
+        assert_eq!((a.level, a.n_balls), (b.level, b.n_balls));
+
+        let mut c = GameState::with_rules(10, 16, 43, rules);
+        play(&mut c, 30);
+        assert_ne!(a.block_map, c.block_map);
+    }
+
+    #[test]
+    fn launch_makes_unit_vectors() {
+        let mut state = GameState::new(10, 16, 7);
+        assert!(!state.launch(0.0, -1e-300));
+        assert!(state.launch(3.0, -4.0));
+        assert_eq!(state.shots, [(0.6, -0.8)]);
+        state.finish_turn();
+        // 回放里记下的不会再变
+        let mut replayed = GameState::new(10, 16, 7);
+        replayed.launch(0.6, -0.8);
+        assert_eq!(replayed.shots, state.shots);
+    }
+}
@@ -0,0 +1,100 @@
+use engine::{Goal, Layout};
+use yew::{classes, function_component, html, use_state, Callback, Html, Properties};
+
+use crate::storage;
+
+/// Levels of the campaign, in the order they unlock. Progress is kept by
+/// name, so levels can be added or reordered without losing it.
+pub const CAMPAIGN: [(&str, &str); 8] = [
+    ("First rows", include_str!("../campaign/01-first-rows.txt")),
+    ("Gather", include_str!("../campaign/02-gather.txt")),
+    (
+        "Clean sweep",
+        include_str!("../campaign/03-clean-sweep.txt"),
+    ),
+    ("Angles", include_str!("../campaign/04-angles.txt")),
+    ("Sparks", include_str!("../campaign/05-sparks.txt")),
+    ("Iron wall", include_str!("../campaign/06-iron-wall.txt")),
+    ("Long haul", include_str!("../campaign/07-long-haul.txt")),
+    ("Finale", include_str!("../campaign/08-finale.txt")),
+];
+
+pub fn campaign_level(i: usize) -> Option<Layout> {
+    CAMPAIGN.get(i)?.1.parse().ok()
+}
+
+/// Where `layout` is in [`CAMPAIGN`], if it is one of its levels.
+pub fn campaign_index(layout: &Layout) -> Option<usize> {
+    (0..CAMPAIGN.len()).find(|&i| campaign_level(i).as_ref() == Some(layout))
+}
+
+/// The first level and the ones after a cleared level can be played.
+pub fn is_unlocked(i: usize, cleared: &[String]) -> bool {
+    i == 0 || cleared.iter().any(|name| name == CAMPAIGN[i - 1].0)
+}
+
+pub fn goal_text(goal: Goal) -> String {
+    match goal {
+        Goal::Clear => String::from("clear the board"),
+        Goal::Survive(turns) => format!("survive {turns} turns"),
+        Goal::Collect(balls) => format!("collect {balls} balls"),
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// starts the level with this index in [`CAMPAIGN`]
+    pub select: Callback<usize>,
+}
+
+#[function_component(CampaignMenu)]
+pub fn campaign_menu(props: &Props) -> Html {
+    let show_menu = use_state(|| false);
+
+    let toggle_cb = {
+        let show_menu = show_menu.clone();
+        Callback::from(move |_| {
+            show_menu.set(!*show_menu);
+        })
+    };
+
+    let cleared = storage::load_campaign();
+
+    html! {
+        <div class="campaign-menu">
+            <button class="toggle-btn" onclick={toggle_cb}>
+                { "🗺️" }
+            </button>
+            if *show_menu {
+                <div class="levels">
+                    { for CAMPAIGN.iter().enumerate().map(|(i, (name, _))| {
+                        let select = props.select.clone();
+                        let show_menu = show_menu.clone();
+                        let onclick = Callback::from(move |_| {
+                            show_menu.set(false);
+                            select.emit(i);
+                        });
+                        let is_cleared = cleared.iter().any(|c| c == name);
+                        let goal = campaign_level(i).and_then(|layout| layout.goal);
+                        html! {
+                            <button
+                                class={classes!(is_cleared.then_some("cleared"))}
+                                disabled={!is_unlocked(i, &cleared)}
+                                {onclick}
+                            >
+                                <span>
+                                    { format!("{}. {}", i + 1, name) }
+                                    if is_cleared { { " ✓" } }
+                                    if !is_unlocked(i, &cleared) { { " 🔒" } }
+                                </span>
+                                if let Some(goal) = goal {
+                                    <span class="level-goal">{ goal_text(goal) }</span>
+                                }
+                            </button>
+                        }
+                    }) }
+                </div>
+            }
+        </div>
+    }
+}
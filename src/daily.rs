@@ -0,0 +1,109 @@
+use engine::{daily_seed, GameState};
+use serde::{Deserialize, Serialize};
+use web_sys::js_sys::Date;
+use web_sys::wasm_bindgen::JsValue;
+use yew::{function_component, html, use_state, Callback, Html, Properties};
+
+use crate::storage;
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// How far a daily game went.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyResult {
+    /// `YYYY-MM-DD` in UTC
+    pub date: String,
+    pub level: u32,
+    pub n_balls: u32,
+    pub n_shots: usize,
+}
+
+impl DailyResult {
+    pub fn new(date: String, gs: &GameState) -> Self {
+        Self {
+            date,
+            level: gs.level,
+            n_balls: gs.n_balls,
+            n_shots: gs.shots.len(),
+        }
+    }
+}
+
+/// The UTC date `days` days ago, `YYYY-MM-DD`.
+pub fn utc_date(days_ago: u32) -> String {
+    let time = Date::now() - days_ago as f64 * DAY_MS;
+    let mut date = String::from(Date::new(&JsValue::from_f64(time)).to_iso_string());
+    date.truncate(10);
+    date
+}
+
+/// The day `gs` is the daily game of, even if it was finished on another.
+pub fn date_of(gs: &GameState) -> Option<String> {
+    gs.daily_date
+        .clone()
+        .filter(|date| gs.mode == engine::GameMode::Daily && gs.seed == daily_seed(date))
+}
+
+/// Days in a row with a result, up to today, or up to yesterday if today's
+/// game is not played yet.
+pub fn streak(results: &[DailyResult]) -> u32 {
+    let played = |days_ago| {
+        let date = utc_date(days_ago);
+        results.iter().any(|r| r.date == date)
+    };
+    let from = if played(0) { 0 } else { 1 };
+    (from..).take_while(|&days_ago| played(days_ago)).count() as u32
+}
+
+/// Text to share the result of the day.
+pub fn summary(result: &DailyResult, streak: u32) -> String {
+    format!(
+        "Bouncy Ball daily {}\nlevel {} · {} balls · {} shots\nstreak {}",
+        result.date, result.level, result.n_balls, result.n_shots, streak,
+    )
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// starts today's game
+    pub play: Callback<()>,
+}
+
+#[function_component(DailyMenu)]
+pub fn daily_menu(props: &Props) -> Html {
+    let show_menu = use_state(|| false);
+
+    let toggle_cb = {
+        let show_menu = show_menu.clone();
+        Callback::from(move |_| {
+            show_menu.set(!*show_menu);
+        })
+    };
+
+    let play = {
+        let show_menu = show_menu.clone();
+        props.play.reform(move |_| show_menu.set(false))
+    };
+
+    let results = storage::load_daily();
+    let today = utc_date(0);
+    let today_result = results.iter().find(|r| r.date == today);
+
+    html! {
+        <div class="daily-menu">
+            <button class="toggle-btn" onclick={toggle_cb}>
+                { "📅" }
+            </button>
+            if *show_menu {
+                <div class="daily">
+                    <h3>{ "Daily " } { &today }</h3>
+                    <p>{ format!("🔥 {} days", streak(&results)) }</p>
+                    if let Some(result) = today_result {
+                        <p>{ format!("best: level {}, {} balls", result.level, result.n_balls) }</p>
+                    }
+                    <button onclick={play}>{ "▶ play" }</button>
+                </div>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,202 @@
+use engine::{BlockShape, Cell, GameState, Layout, BALL_R, BLOCK_SIZE};
+use web_sys::{Event, HtmlInputElement, HtmlTextAreaElement};
+use yew::{
+    function_component, html, use_node_ref, use_state, Callback, Html, Properties, TargetCast,
+};
+
+use crate::painter::Painter;
+
+/// hp of blocks put by a click, until another one is typed
+const DEFAULT_HP: i32 = 10;
+const MAX_HP: i32 = 9999;
+
+/// A [`Layout`] being edited in place of the game shown by the `Game`
+/// component.
+pub struct Editor {
+    pub layout: Layout,
+    /// the cell typed into from the keyboard
+    pub selected: Option<(usize, usize)>,
+    /// hp of the next block put by a click
+    hp: i32,
+    /// whether a number was typed since the cell was selected
+    typed: bool,
+    /// the game on screen before editing, shown again on exit
+    pub live_state: GameState,
+}
+
+impl Editor {
+    /// Starts editing the board of `live_state`.
+    pub fn new(live_state: GameState) -> Self {
+        Self {
+            layout: Layout::from_state(&live_state),
+            selected: None,
+            hp: DEFAULT_HP,
+            typed: false,
+            live_state,
+        }
+    }
+
+    /// The layout as a game, to draw or play it.
+    pub fn preview(&self) -> GameState {
+        GameState::from_layout(
+            self.layout.clone(),
+            self.live_state.seed,
+            self.live_state.rules,
+        )
+    }
+
+    pub fn draw(&self, painter: &Painter) {
+        painter.draw_basic(&self.preview(), true);
+        if let Some((i, j)) = self.selected {
+            painter.draw_selection(i, j);
+        }
+    }
+
+    /// Clicked at `(x, y)` on the board: the bottom row moves the start ball,
+    /// other cells go from empty to block to ball to get.
+    pub fn click(&mut self, x: f64, y: f64) {
+        let (i, j) = ((y / BLOCK_SIZE) as usize, (x / BLOCK_SIZE) as usize);
+        if j >= self.layout.mw || i >= self.layout.mh {
+            return;
+        }
+        // 最下面一排不会有块，用来放球
+        if i == self.layout.mh - 1 {
+            let ww = self.layout.mw as f64 * BLOCK_SIZE;
+            self.layout.start_x = x.clamp(BALL_R, ww - BALL_R);
+            self.selected = None;
+            return;
+        }
+
+        let cell = &mut self.layout.cells[i][j];
+        *cell = match *cell {
+            Cell::Empty => Cell::Block { hp: self.hp },
+            Cell::Block { .. } => Cell::BallPickup,
+            _ => Cell::Empty,
+        };
+        self.layout.shapes[i][j] = BlockShape::Square;
+        self.selected = Some((i, j));
+        self.typed = false;
+    }
+
+    /// Types `key` into the selected block: digits set its hp, `Backspace`
+    /// removes the last one and `Delete` empties the cell.
+    pub fn key(&mut self, key: &str) -> bool {
+        let Some((i, j)) = self.selected else {
+            return false;
+        };
+        let cell = &mut self.layout.cells[i][j];
+        if key == "Delete" {
+            *cell = Cell::Empty;
+            return true;
+        }
+        let Cell::Block { hp } = cell else {
+            return false;
+        };
+        let old = if self.typed { *hp } else { 0 };
+        *hp = match key {
+            "Backspace" => (old / 10).max(1),
+            _ => match key.parse::<i32>() {
+                Ok(digit) if key.len() == 1 => (old * 10 + digit).clamp(1, MAX_HP),
+                _ => return false,
+            },
+        };
+        self.typed = true;
+        self.hp = *hp;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        let empty = Layout::empty(self.layout.mw, self.layout.mh);
+        self.layout.cells = empty.cells;
+        self.layout.shapes = empty.shapes;
+        self.selected = None;
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// the layout written out, see [`Layout`]
+    pub text: String,
+    pub n_balls: u32,
+    pub n_balls_onchange: Callback<u32>,
+    /// `None` for endless, see [`Layout::max_shots`]
+    pub max_shots: Option<u32>,
+    pub max_shots_onchange: Callback<Option<u32>>,
+    pub import: Callback<Layout>,
+    pub clear: Callback<()>,
+    pub play: Callback<()>,
+    pub exit: Callback<()>,
+}
+
+#[function_component(EditorBar)]
+pub fn editor_bar(props: &Props) -> Html {
+    let n_balls_onchange = props.n_balls_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number().max(1.0) as u32
+    });
+
+    // 空着就不是谜题
+    let max_shots_onchange = props.max_shots_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        let shots = input.value_as_number();
+        shots.is_finite().then_some(shots.max(1.0) as u32)
+    });
+
+    let import_ref = use_node_ref();
+    let import_error = use_state(|| None::<String>);
+    let import_onclick = {
+        let import_ref = import_ref.clone();
+        let import_error = import_error.clone();
+        let import = props.import.clone();
+        Callback::from(move |_| {
+            let Some(input) = import_ref.cast::<HtmlTextAreaElement>() else {
+                return;
+            };
+            match input.value().parse::<Layout>() {
+                Ok(layout) => {
+                    import_error.set(None);
+                    import.emit(layout);
+                }
+                Err(err) => import_error.set(Some(err.to_string())),
+            }
+        })
+    };
+
+    html! {
+        <div class="editor-bar">
+            <p class="editor-hint">
+                { "click a cell for block / ball / empty, type its hp, click the bottom row to move the start" }
+            </p>
+            <div class="editor-buttons">
+                <label>{ "balls" }</label>
+                <input
+                    type="number"
+                    class="balls-input"
+                    value={props.n_balls.to_string()}
+                    min={1}
+                    onchange={n_balls_onchange}
+                />
+                <label>{ "shots" }</label>
+                <input
+                    type="number"
+                    class="balls-input"
+                    value={props.max_shots.map_or(String::new(), |shots| shots.to_string())}
+                    min={1}
+                    placeholder="∞"
+                    onchange={max_shots_onchange}
+                />
+                <button onclick={props.clear.reform(|_| ())}>{ "Clear" }</button>
+                <button onclick={props.play.reform(|_| ())}>{ "▶ play" }</button>
+                <button onclick={props.exit.reform(|_| ())}>{ "✕" }</button>
+            </div>
+            <div class="editor-text">
+                <textarea readonly=true value={props.text.clone()} />
+                <textarea placeholder="paste a layout" ref={import_ref} />
+                <button onclick={import_onclick}>{ "import" }</button>
+            </div>
+            if let Some(err) = &*import_error {
+                <div class="replay-error">{ err }</div>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo_render::{request_animation_frame, AnimationFrame};
+
+/// Longest frame taken into account, so a tab coming back from the
+/// background does not fast forward the game.
+const MAX_FRAME_MS: f64 = 100.0;
+
+/// Calls a closure on every animation frame with the milliseconds since the
+/// previous one, until it returns `false` or this is dropped.
+pub struct FrameLoop {
+    frame: Rc<RefCell<Option<AnimationFrame>>>,
+}
+
+impl FrameLoop {
+    pub fn start(on_frame: impl FnMut(f64) -> bool + 'static) -> Self {
+        let frame = Rc::new(RefCell::new(None));
+        schedule(frame.clone(), None, Rc::new(RefCell::new(on_frame)));
+        Self { frame }
+    }
+}
+
+impl Drop for FrameLoop {
+    fn drop(&mut self) {
+        self.frame.borrow_mut().take();
+    }
+}
+
+fn schedule(
+    frame: Rc<RefCell<Option<AnimationFrame>>>,
+    last: Option<f64>,
+    on_frame: Rc<RefCell<dyn FnMut(f64) -> bool>>,
+) {
+    let next = {
+        let frame = frame.clone();
+        request_animation_frame(move |now| {
+            let dt = last.map_or(0.0, |last| (now - last).clamp(0.0, MAX_FRAME_MS));
+            if (on_frame.borrow_mut())(dt) {
+                schedule(frame, Some(now), on_frame);
+            }
+        })
+    };
+    *frame.borrow_mut() = Some(next);
+}
@@ -1,37 +1,44 @@
-use std::collections::VecDeque;
-
 // use gloo_console::log;
-use gloo_timers::callback::Interval;
-use rand::seq::SliceRandom;
-use rand::Rng;
-use web_sys::js_sys::Array;
-use web_sys::wasm_bindgen::JsValue;
-use web_sys::{CanvasRenderingContext2d, Event, HtmlCanvasElement, HtmlImageElement, PointerEvent};
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use engine::{
+    BallMovingStatus, GameState, Layout, Replay, Rules, StepOutcome, BALL_R, BALL_SIZE, BLOCK_SIZE,
+    STEP_DIST,
+};
+use web_sys::{Event, HtmlCanvasElement, HtmlImageElement, KeyboardEvent, PointerEvent};
 use yew::{
-    classes, function_component, html, use_effect_with, use_mut_ref, use_node_ref, use_state,
-    Callback, Html, Properties, TargetCast,
+    classes, function_component, html, use_effect_with, use_memo, use_mut_ref, use_node_ref,
+    use_state, Callback, Html, NodeRef, Properties, TargetCast,
 };
 
-use crate::settings::Settings;
-
-const BALL_SIZE: f64 = 36.0;
-const BALL_R: f64 = BALL_SIZE / 2.0;
-const BLOCK_SIZE: f64 = 100.0;
-const BLOCK_BORDER: f64 = 6.0;
-
-const MICKEY_W: f64 = 924.0;
-const MICKEY_H: f64 = 864.0;
-
-const BG_COLOR: &str = "#3030ff";
+use crate::campaign::{campaign_index, campaign_level, goal_text, CampaignMenu, CAMPAIGN};
+use crate::daily::{date_of, streak, summary, utc_date, DailyMenu, DailyResult};
+use crate::editor::{Editor, EditorBar};
+use crate::frame_loop::FrameLoop;
+use crate::gamepad;
+use crate::painter::Painter;
+use crate::puzzles::{puzzle, puzzle_index, PuzzleMenu, PUZZLES};
+use crate::replay::{ReplayBar, ReplayPlayer};
+use crate::scores::{ScoreEntry, ScoreTable, Trophy};
+use crate::settings::{speed_to_v, Preferences, Settings};
+use crate::storage;
 
-const INTERV: u32 = 8;
-const NEXT_BALL_TIME_DIST: f64 = 3.0 * BALL_SIZE;
+/// speed `v` is the distance a ball travels in this many milliseconds
+const INTERV: f64 = 8.0;
 
-const NEW_BALL_ID: i32 = -1;
+/// how much faster a turn plays while fast forwarding
+const FAST_FORWARD: f64 = 4.0;
 
 const FULL_RESOURCE: u8 = 3;
 
-const EPS: f64 = 1e-10;
+/// aim steps of the arrow keys, with and without shift
+const FINE_AIM_STEP: f64 = PI / 900.0;
+const COARSE_AIM_STEP: f64 = PI / 60.0;
+/// drags starting further than this part of the board height from the
+/// start ball turn the aim finely, closer ones point as usual
+const PRECISION_REACH: f64 = 0.8;
+/// how much less a precision drag turns the aim than pointing would
+const PRECISION_SCALE: f64 = 0.2;
 
 macro_rules! clone_all {
     [$($s:ident), * $(,)?] => {
@@ -41,404 +48,135 @@ macro_rules! clone_all {
     };
 }
 
-#[derive(Debug)]
-enum BallMovingStatus {
-    Runing,
-    Backing,
-    Done,
-}
-
-#[derive(Debug)]
-struct BallStatus {
-    x: f64,
-    y: f64,
-    to_up: bool,
-    // 只是表示与初始方向是否一致
-    // 向右的速度也可能是负的
-    to_right: bool,
-    moving_status: BallMovingStatus,
-}
-
+/// Turns frame time into whole engine steps, so the game plays the same at
+/// any frame rate, and keeps where the balls were before the last step to
+/// draw them in between.
 #[derive(Default)]
-struct MapStatus {
-    ctx: Option<CanvasRenderingContext2d>,
-    img: Option<HtmlImageElement>,
-    mickey: Option<HtmlImageElement>,
-    moving_balls: Vec<BallStatus>,
-    block_map: VecDeque<Vec<i32>>,
-    n_waiting_bolls: u32,
-    mw: usize,
-    mh: usize,
-    waiting_next: u32,
-    vx: f64,
-    vy: f64,
-    start_x: f64,
-    new_start_x: Option<f64>,
+pub(crate) struct Stepper {
+    /// steps owed to the engine, the fraction is how far into the next one
+    /// the frame is
+    acc: f64,
+    prev: Vec<(f64, f64)>,
 }
 
-impl MapStatus {
-    fn update_blocks_and_check_game_over(&mut self, n_rank: usize) -> bool {
-        if self.block_map.pop_back().is_none() {
-            return false;
+impl Stepper {
+    /// Runs the steps `dt` milliseconds at speed `v` are worth.
+    pub fn advance(&mut self, gs: &mut GameState, v: f64, dt: f64) -> StepOutcome {
+        self.acc += dt / INTERV * v / STEP_DIST;
+        let mut outcome = StepOutcome::default();
+        while self.acc >= 1.0 && !outcome.turn_done {
+            self.prev.clear();
+            self.prev
+                .extend(gs.moving_balls.iter().map(|ball| (ball.x, ball.y)));
+            let step = gs.step();
+            outcome.new_balls += step.new_balls;
+            outcome.turn_done = step.turn_done;
+            self.acc -= 1.0;
         }
-
-        let mut rng = rand::thread_rng();
-        let n = rng.gen_range(
-            (n_rank / 15 + 1).min(self.mw / 3)..(n_rank / 6 + n_rank.min(3) + 2).min(self.mw - 2),
-        );
-        let mut new_line: Vec<i32> = (0..self.mw)
-            .map(|idx| if idx < n { n_rank as i32 } else { 0 })
-            .collect();
-        if rng.gen_bool(0.8) {
-            new_line[n] = NEW_BALL_ID;
+        if outcome.turn_done {
+            self.reset();
         }
-        new_line.shuffle(&mut rng);
-        self.block_map.push_front(new_line);
-
-        self.draw_basic(true);
-
-        self.block_map.back().unwrap().iter().any(|v| *v > 0)
-    }
-
-    fn draw_ball(&self, ox: f64, oy: f64) {
-        let (Some(ctx), Some(img)) = (self.ctx.as_ref(), self.img.as_ref()) else {
-            return;
-        };
-        ctx.draw_image_with_html_image_element_and_dw_and_dh(
-            img,
-            ox - BALL_R,
-            oy - BALL_R,
-            BALL_SIZE,
-            BALL_SIZE,
-        )
-        .expect("draw next ball failed");
-    }
-
-    fn block_color(&self, v: i32) -> String {
-        format!(
-            "rgb({}, {}, {})",
-            20 + v % 20 * 13,
-            (80.0 + ((v as f64 / 31.0).sin()) * 80.0) as u8,
-            (110.0 + ((v as f64 / 47.0).cos()) * 80.0) as u8,
-        )
+        outcome
     }
 
-    fn draw_block(&self, i: usize, j: usize, v: i32) {
-        // TODO: use offscreen canvas
-        let Some(ctx) = self.ctx.as_ref() else { return };
-        if v > 0 {
-            let x = j as f64 * BLOCK_SIZE;
-            let y = i as f64 * BLOCK_SIZE;
-
-            ctx.set_fill_style(&JsValue::from_str("#e0e0e0"));
-            ctx.begin_path();
-            ctx.move_to(x, y);
-            ctx.line_to(x + BLOCK_SIZE, y);
-            ctx.line_to(x, y + BLOCK_SIZE);
-            ctx.fill();
-
-            ctx.set_fill_style(&JsValue::from_str("#202020"));
-            ctx.begin_path();
-            ctx.move_to(x + BLOCK_SIZE, y);
-            ctx.line_to(x, y + BLOCK_SIZE);
-            ctx.line_to(x + BLOCK_SIZE, y + BLOCK_SIZE);
-            ctx.fill();
-
-            ctx.set_fill_style(&JsValue::from_str(&self.block_color(v)));
-            ctx.fill_rect(
-                x + BLOCK_BORDER,
-                y + BLOCK_BORDER,
-                BLOCK_SIZE - BLOCK_BORDER - BLOCK_BORDER,
-                BLOCK_SIZE - BLOCK_BORDER - BLOCK_BORDER,
-            );
-            let text = v.to_string();
-            ctx.set_fill_style(&JsValue::from_str("white"));
-            ctx.fill_text(
-                &text,
-                x + (BLOCK_SIZE - ctx.measure_text(&text).unwrap().width()) / 2.0,
-                y + BLOCK_SIZE / 2.0,
-            )
-            .unwrap();
-        } else if v == NEW_BALL_ID {
-            let Some(img) = self.img.as_ref() else { return };
-            ctx.draw_image_with_html_image_element_and_dw_and_dh(
-                img,
-                j as f64 * BLOCK_SIZE + BLOCK_SIZE / 4.0,
-                i as f64 * BLOCK_SIZE + BLOCK_SIZE / 4.0,
-                BLOCK_SIZE / 2.0,
-                BLOCK_SIZE / 2.0,
-            )
-            .expect("draw ball to get failed");
-        }
+    pub fn reset(&mut self) {
+        self.acc = 0.0;
+        self.prev.clear();
     }
 
-    fn draw_aimline(&mut self) {
-        self.draw_basic(true);
-        let hh = self.mh as f64 * BLOCK_SIZE;
-        let (ox, oy) = (self.start_x, hh - BALL_R);
-        self.move_one_ball(
-            &mut BallStatus {
-                x: ox,
-                y: oy,
-                to_up: true,
-                to_right: true,
-                moving_status: BallMovingStatus::Runing,
-            },
-            self.vx * 5000.0,
-            self.vy * 5000.0,
-            5,
-            true,
-        );
+    /// Where to draw the moving balls, between their last two steps.
+    pub fn ball_positions<'a>(
+        &'a self,
+        gs: &'a GameState,
+    ) -> impl Iterator<Item = (f64, f64)> + 'a {
+        let alpha = self.acc.fract();
+        gs.moving_balls
+            .iter()
+            .enumerate()
+            .filter(|(_, ball)| ball.moving_status != BallMovingStatus::Gone)
+            .map(move |(i, ball)| match self.prev.get(i) {
+                Some(&(px, py)) => (px + (ball.x - px) * alpha, py + (ball.y - py) * alpha),
+                // 刚发出去的球
+                None => (ball.x, ball.y),
+            })
     }
+}
 
-    fn draw_basic(&self, with_start_ball: bool) {
-        let ww = self.mw as f64 * BLOCK_SIZE;
-        let hh = self.mh as f64 * BLOCK_SIZE;
-        let Some(ctx) = self.ctx.as_ref() else { return };
-        ctx.set_fill_style(&JsValue::from_str(BG_COLOR));
-        ctx.fill_rect(0.0, 0.0, ww, hh);
-        if let Some(mickey) = self.mickey.as_ref() {
-            let mick_w = ww;
-            let mick_h = mick_w * MICKEY_H / MICKEY_W;
-            ctx.draw_image_with_html_image_element_and_dw_and_dh(
-                mickey,
-                0.0,
-                (hh - mick_h) / 2.0,
-                mick_w,
-                mick_h,
-            )
-            .expect("draw mickey failed");
-        }
-        for i in 0..self.mh {
-            for j in 0..self.mw {
-                self.draw_block(i, j, self.block_map[i][j])
-            }
-        }
-        if with_start_ball {
-            self.draw_ball(self.start_x, hh - BALL_R)
-        };
-    }
-
-    pub fn move_one_ball(
-        &mut self,
-        ball: &mut BallStatus,
-        lx: f64,
-        ly: f64,
-        max_bounces: u32,
-        is_aimline: bool,
-    ) -> u32 {
-        let mut rest_lx = lx;
-        let mut rest_ly = ly;
-        let mut rest_bounces = max_bounces;
-        let mut new_ball = 0;
-
-        let Some(ctx) = self.ctx.as_ref() else {
-            return 0;
-        };
-        if is_aimline {
-            ctx.begin_path();
-            ctx.move_to(ball.x, ball.y);
-        }
-
-        while rest_lx.abs() > EPS && rest_ly.abs() > EPS && rest_bounces > 0 {
-            let pi = (ball.y + (BALL_R + EPS).copysign(rest_ly)).div_euclid(BLOCK_SIZE) as usize;
-            let pj = (ball.x + (BALL_R + EPS).copysign(rest_lx)).div_euclid(BLOCK_SIZE) as usize;
-
-            let max_lx = BLOCK_SIZE.mul_add(
-                if rest_lx.is_sign_positive() {
-                    pj + 1
-                } else {
-                    pj
-                } as f64,
-                -ball.x - BALL_R.copysign(rest_lx),
-            );
-
-            let max_ly = BLOCK_SIZE.mul_add(
-                if rest_ly.is_sign_positive() {
-                    pi + 1
-                } else {
-                    pi
-                } as f64,
-                -ball.y - BALL_R.copysign(rest_ly),
-            );
-
-            let (lx, ly, reach_x, reach_y) =
-                if rest_lx.abs() < max_lx.abs() && rest_ly.abs() < max_ly.abs() {
-                    (rest_lx, rest_ly, false, false)
-                } else if (max_lx * rest_ly).abs() < (max_ly * rest_lx).abs() {
-                    (max_lx, max_lx / rest_lx * rest_ly, true, false)
-                } else {
-                    (max_ly / rest_ly * rest_lx, max_ly, false, true)
-                };
-
-            rest_lx -= lx;
-            rest_ly -= ly;
-
-            ball.x += lx;
-            ball.y += ly;
-
-            // 移动前后pi pj不会变，但是real pi/pj可能会，所以移动后再算
-            let rpi = ball.y.div_euclid(BLOCK_SIZE) as usize;
-            let rpj = ball.x.div_euclid(BLOCK_SIZE) as usize;
-
-            if !is_aimline && self.block_map[rpi][rpj] == NEW_BALL_ID {
-                self.block_map[rpi][rpj] = 0;
-                new_ball += 1;
-            }
-
-            let next_pj = if lx.is_sign_positive() {
-                (pj < self.mw - 1).then_some(pj + 1)
-            } else {
-                (pj > 0).then_some(pj - 1)
-            };
-
-            let next_pi = if ly.is_sign_positive() {
-                (pi < self.mh - 1).then_some(pi + 1)
-            } else {
-                (pi > 0).then_some(pi - 1)
-            };
-
-            if reach_x {
-                if let Some(next_pj) = next_pj {
-                    if self.block_map[rpi][next_pj] > 0 {
-                        ball.to_right = !ball.to_right;
-                        rest_lx = -rest_lx;
-                        if !is_aimline {
-                            self.block_map[rpi][next_pj] -= 1;
-                        }
-                        rest_bounces -= 1;
-                    } else if self.block_map[pi][next_pj] > 0 {
-                        // 撞角近似为撞边
-                        ball.to_right = !ball.to_right;
-                        rest_lx = -rest_lx;
-                        if !is_aimline {
-                            self.block_map[pi][next_pj] -= 1;
-                        }
-                        rest_bounces -= 1;
-                    }
-                } else {
-                    ball.to_right = !ball.to_right;
-                    rest_lx = -rest_lx;
-                    rest_bounces -= 1;
-                }
-            }
-
-            if reach_y {
-                if let Some(next_pi) = next_pi {
-                    if self.block_map[next_pi][rpj] > 0 {
-                        ball.to_up = !ball.to_up;
-                        rest_ly = -rest_ly;
-                        if !is_aimline {
-                            self.block_map[next_pi][rpj] -= 1;
-                        }
-                        rest_bounces -= 1;
-                    } else if self.block_map[next_pi][pj] > 0 {
-                        ball.to_up = !ball.to_up;
-                        rest_ly = -rest_ly;
-                        if !is_aimline {
-                            self.block_map[next_pi][pj] -= 1;
-                        }
-                        rest_bounces -= 1;
-                    }
-                } else {
-                    ball.to_up = !ball.to_up;
-                    rest_ly = -rest_ly;
-                    rest_bounces -= 1;
-                }
-
-                if pi == self.mh - 1 && ly.is_sign_positive() {
-                    rest_lx = 0.0;
-                    rest_ly = 0.0;
-                    if self.new_start_x.is_some() {
-                        ball.moving_status = BallMovingStatus::Backing;
-                    } else {
-                        if !is_aimline {
-                            self.new_start_x = Some(ball.x);
-                        }
-                        ball.moving_status = BallMovingStatus::Done;
-                    }
-                }
-            }
-
-            if is_aimline {
-                ctx.line_to(ball.x, ball.y);
-            }
-        }
+fn random_seed() -> u64 {
+    rand::random::<u32>() as u64
+}
 
-        if is_aimline {
-            ctx.stroke();
-        }
+/// Keeps `angle` at least `min_angle` above the floor on both sides.
+fn clamp_aim(angle: f64, min_angle: f64) -> f64 {
+    angle.clamp(min_angle, PI - min_angle)
+}
 
-        new_ball
-    }
+/// The pointer seen from the start ball, on the board, y downwards.
+fn start_offset(event: &PointerEvent, canvas_ref: &NodeRef, state: &GameState) -> (f64, f64) {
+    let (x, y) = board_point(event, canvas_ref, state.mw);
+    (
+        x - state.start_x,
+        y - (state.mh as f64 * BLOCK_SIZE - BALL_R),
+    )
+}
 
-    pub fn simulate_moving(&mut self, v: f64) -> (u32, bool) {
-        let hh = self.mh as f64 * BLOCK_SIZE;
+/// Angle from the start ball to the pointer, `None` if it is too low.
+fn aim_direction(event: &PointerEvent, canvas_ref: &NodeRef, state: &GameState) -> Option<f64> {
+    let (dx, dy) = start_offset(event, canvas_ref, state);
+    (dy < -2.0 * BALL_R).then(|| (-dy).atan2(dx))
+}
 
-        let mut new_ball = 0;
+/// A precision drag, `None` unless the pointer went down far from the start
+/// ball: the angle of the pointer then and the aim to turn from.
+fn precision_drag_from(
+    event: &PointerEvent,
+    canvas_ref: &NodeRef,
+    state: &GameState,
+    aim: f64,
+) -> Option<(f64, f64)> {
+    let (dx, dy) = start_offset(event, canvas_ref, state);
+    let aim = aim_direction(event, canvas_ref, state).unwrap_or(aim);
+    let reach = PRECISION_REACH * state.mh as f64 * BLOCK_SIZE;
+    (dx.hypot(dy) > reach).then_some(((-dy).atan2(dx), aim))
+}
 
-        let mut balls = std::mem::take(&mut self.moving_balls);
-        for ball in balls.iter_mut() {
-            match ball.moving_status {
-                BallMovingStatus::Done => {}
-                BallMovingStatus::Backing => {
-                    let new_start_x = self.new_start_x.unwrap();
-                    let rest_lx = new_start_x - ball.x;
-                    if rest_lx.abs() < EPS {
-                        ball.moving_status = BallMovingStatus::Done;
-                    } else {
-                        ball.x += rest_lx.abs().min(v.abs() * 0.678).copysign(rest_lx);
-                    }
-                }
-                BallMovingStatus::Runing => {
-                    new_ball += self.move_one_ball(
-                        ball,
-                        v * if ball.to_right { self.vx } else { -self.vx },
-                        v * if ball.to_up { self.vy } else { -self.vy },
-                        1000,
-                        false,
-                    );
-                }
-            }
-        }
-        self.moving_balls = balls;
-
-        if self.n_waiting_bolls > 0 {
-            if self.waiting_next == 0 {
-                let go_more = self.moving_balls.len() as f64
-                    / (self.n_waiting_bolls as f64 + self.moving_balls.len() as f64)
-                    / 10.0;
-                self.moving_balls.push(BallStatus {
-                    x: self.start_x + self.vx * NEXT_BALL_TIME_DIST * go_more,
-                    y: hh - BALL_R + self.vy * NEXT_BALL_TIME_DIST * go_more,
-                    to_up: true,
-                    to_right: true,
-                    moving_status: BallMovingStatus::Runing,
-                });
-                self.n_waiting_bolls -= 1;
-                self.waiting_next = (NEXT_BALL_TIME_DIST / v) as u32;
-            } else {
-                self.waiting_next -= 1;
+/// Where the pointer aims: at itself, or during a precision `drag` the aim
+/// it started from, turned by a fraction of how much the pointer turned
+/// around the start ball. The further away, the finer, and the pointer may
+/// go below the start ball, only letting go on it cancels.
+fn pointer_aim(
+    event: &PointerEvent,
+    canvas_ref: &NodeRef,
+    state: &GameState,
+    drag: Option<(f64, f64)>,
+    min_angle: f64,
+) -> Option<f64> {
+    let angle = match drag {
+        Some((from, aim)) => {
+            let (dx, dy) = start_offset(event, canvas_ref, state);
+            if dx.hypot(dy) < BALL_SIZE {
+                return None;
             }
+            // 转过的角度，跨过 ±π 也不跳
+            let turn = ((-dy).atan2(dx) - from + PI).rem_euclid(2.0 * PI) - PI;
+            aim + turn * PRECISION_SCALE
         }
+        None => aim_direction(event, canvas_ref, state)?,
+    };
+    Some(clamp_aim(angle, min_angle))
+}
 
-        self.draw_basic(self.n_waiting_bolls > 0);
-        self.moving_balls.iter().for_each(|ball| {
-            self.draw_ball(ball.x, ball.y);
-        });
-
-        let done = self.n_waiting_bolls == 0
-            && self
-                .moving_balls
-                .iter()
-                .all(|ball| matches!(ball.moving_status, BallMovingStatus::Done));
-        if done {
-            self.start_x = self.new_start_x.unwrap();
-            self.new_start_x = None;
-        }
-
-        (new_ball, done)
-    }
+/// Where the pointer is on a `mw` blocks wide board.
+fn board_point(event: &PointerEvent, canvas_ref: &NodeRef, mw: usize) -> (f64, f64) {
+    let canvas = canvas_ref
+        .cast::<HtmlCanvasElement>()
+        .expect("canvas_ref not attached");
+    let rect = canvas.get_bounding_client_rect();
+    let ratio = rect.width() / (mw as f64 * BLOCK_SIZE);
+    (
+        (event.client_x() as f64 - rect.left()) / ratio,
+        (event.client_y() as f64 - rect.top()) / ratio,
+    )
 }
 
 #[derive(Properties, PartialEq)]
@@ -448,11 +186,14 @@ pub struct Props {
     /// map height, numher for blocks
     pub mh: usize,
     pub is_full: bool,
+    /// seed of the first game, a random one if not given
+    pub seed: Option<u64>,
+    /// level of the first game instead of random rows, see [`Layout`]
+    pub level: Option<String>,
 }
 
 #[function_component(Game)]
 pub fn game(props: &Props) -> Html {
-    let n_balls = use_mut_ref(|| 0_u32);
     let n_balls_to_show = use_state(|| 0_u32);
     let level = use_state(|| 1_u32);
 
@@ -460,193 +201,985 @@ pub fn game(props: &Props) -> Html {
 
     let is_moving = use_state(|| false);
     let is_game_over = use_state(|| false);
+    let score_rank = use_state(|| None::<usize>);
     let is_draw_aimline = use_mut_ref(|| false);
-
-    let map_status = use_mut_ref(MapStatus::default);
-    let simulation_interval = use_mut_ref(|| None);
+    // 瞄准角度，从右往左
+    let aim_angle = use_mut_ref(|| FRAC_PI_2);
+    // 精细拖动：离发球点很远按下时，指针绕发球点的角度和那时的瞄准角度
+    let precision_drag = use_mut_ref(|| None::<(f64, f64)>);
+
+    // 上次没玩完的，除非链接里指定了 seed 或关卡
+    let link_level = use_memo(props.level.clone(), |level| {
+        level.as_deref().map(str::parse::<Layout>)
+    });
+    let level_error = use_state(|| match link_level.as_ref() {
+        Some(Err(err)) => Some(err.to_string()),
+        _ => None,
+    });
+    let resume = use_mut_ref(|| match link_level.as_ref() {
+        Some(Ok(layout)) => Some(GameState::from_layout(
+            layout.clone(),
+            props.seed.unwrap_or_else(random_seed),
+            storage::load_settings().rules(),
+        )),
+        _ => props.seed.is_none().then(storage::load_game).flatten(),
+    });
+    // 载入关卡时加一，总要开新局
+    let level_loads = use_state(|| 0_u32);
+    let seed = use_state(|| {
+        resume
+            .borrow()
+            .as_ref()
+            .map(|gs| gs.seed)
+            .or(props.seed)
+            .unwrap_or_else(random_seed)
+    });
+    let game_state = use_mut_ref(|| GameState::new(props.mw, props.mh, *seed));
+    let painter = use_mut_ref(Painter::default);
+    let simulation_loop = use_mut_ref(|| None);
+    let stepper = use_mut_ref(Stepper::default);
+    let fast_forward = use_mut_ref(|| false);
+    let is_fast_forward = use_state(|| false);
+    let recall_requested = use_mut_ref(|| false);
+
+    let replay_player = use_mut_ref(|| None::<ReplayPlayer>);
+    let replay_loop = use_mut_ref(|| None);
+    let is_replaying = use_state(|| false);
+    let is_replay_paused = use_state(|| false);
+
+    // 编辑器，试玩时也还在
+    let editor = use_mut_ref(|| None::<Editor>);
+    let is_editing = use_state(|| false);
+    let layout_text = use_state(String::new);
+
+    // 悔一步：出手前的局面
+    let undo_snapshot = use_mut_ref(|| None::<GameState>);
+    let can_undo = use_state(|| false);
 
     let resource_state = use_state(|| 0_u8);
 
-    let v = use_mut_ref(|| 8.0);
-    let mw = use_state(|| props.mw);
-    let mh = use_state(|| props.mh);
+    let prefs = use_state(storage::load_settings);
+    let v = use_mut_ref(|| speed_to_v(prefs.speed));
+    let mw = use_state(|| {
+        resume
+            .borrow()
+            .as_ref()
+            .map(|gs| gs.mw)
+            .or(prefs.mw)
+            .unwrap_or(props.mw)
+    });
+    let mh = use_state(|| {
+        resume
+            .borrow()
+            .as_ref()
+            .map(|gs| gs.mh)
+            .or(prefs.mh)
+            .unwrap_or(props.mh)
+    });
+
+    let update_prefs = {
+        let prefs = prefs.clone();
+        Callback::from(move |new_prefs: Preferences| {
+            storage::save_settings(&new_prefs);
+            prefs.set(new_prefs);
+        })
+    };
 
-    let v_onchange = {
-        let v = v.clone();
-        Callback::from(move |new_v| {
-            *v.borrow_mut() = new_v;
+    let speed_onchange = {
+        clone_all![v, prefs, update_prefs];
+        Callback::from(move |speed| {
+            *v.borrow_mut() = speed_to_v(speed);
+            update_prefs.emit(Preferences {
+                speed,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let special_chance_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |special_chance| {
+            update_prefs.emit(Preferences {
+                special_chance,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let special_weights_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |special_weights| {
+            update_prefs.emit(Preferences {
+                special_weights,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let obstacle_level_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |obstacle_level| {
+            update_prefs.emit(Preferences {
+                obstacle_level,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let min_aim_angle_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |min_aim_angle| {
+            update_prefs.emit(Preferences {
+                min_aim_angle,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let undo_budget_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |undo_budget| {
+            update_prefs.emit(Preferences {
+                undo_budget,
+                ..(*prefs).clone()
+            });
+        })
+    };
+    let difficulty_onchange = {
+        clone_all![prefs, update_prefs];
+        Callback::from(move |difficulty| {
+            update_prefs.emit(Preferences {
+                difficulty,
+                ..(*prefs).clone()
+            });
         })
     };
     let mw_onchange = {
-        let mw = mw.clone();
-        Callback::from(move |w| mw.set(w))
+        clone_all![mw, prefs, update_prefs];
+        Callback::from(move |w| {
+            mw.set(w);
+            update_prefs.emit(Preferences {
+                mw: Some(w),
+                ..(*prefs).clone()
+            });
+        })
     };
 
     let mh_onchange = {
-        let mh = mh.clone();
-        Callback::from(move |h| mh.set(h))
+        clone_all![mh, prefs, update_prefs];
+        Callback::from(move |h| {
+            mh.set(h);
+            update_prefs.emit(Preferences {
+                mh: Some(h),
+                ..(*prefs).clone()
+            });
+        })
+    };
+
+    let reset_settings = {
+        clone_all![v, prefs, mw, mh];
+        let (default_mw, default_mh) = (props.mw, props.mh);
+        Callback::from(move |_| {
+            storage::clear_settings();
+            let defaults = Preferences::default();
+            *v.borrow_mut() = speed_to_v(defaults.speed);
+            prefs.set(defaults);
+            mw.set(default_mw);
+            mh.set(default_mh);
+        })
+    };
+
+    let seed_onchange = {
+        let seed = seed.clone();
+        Callback::from(move |s| seed.set(s))
     };
 
     // 瞄准
+    let min_angle = prefs.min_aim_angle.to_radians();
     let draw_aimline = {
-        clone_all![is_draw_aimline, canvas_ref, map_status];
+        clone_all![
+            is_draw_aimline,
+            canvas_ref,
+            game_state,
+            painter,
+            aim_angle,
+            precision_drag,
+        ];
         Callback::from(move |event: PointerEvent| {
             if !*is_draw_aimline.borrow() {
                 return;
             }
-            let (x, y) = (event.client_x() as f64, event.client_y() as f64);
-
-            let canvas = canvas_ref
-                .cast::<HtmlCanvasElement>()
-                .expect("canvas_ref not attached");
-            let rect = canvas.get_bounding_client_rect();
-            let ratio = rect.width() / (map_status.borrow().mw as f64 * BLOCK_SIZE);
-
-            let (ox, oy) = (
-                rect.left() + map_status.borrow().start_x * ratio,
-                rect.bottom() - BALL_R * ratio,
-            );
-
-            let (dx, dy) = (x - ox, y - oy);
-            if let Ok(mut ms) = map_status.try_borrow_mut() {
-                if dy > -2.0 * BALL_R * ratio {
-                    ms.draw_basic(true);
-                    return;
+            if let Ok(mut gs) = game_state.try_borrow_mut() {
+                let drag = *precision_drag.borrow();
+                match pointer_aim(&event, &canvas_ref, &gs, drag, min_angle) {
+                    Some(angle) => {
+                        *aim_angle.borrow_mut() = angle;
+                        painter.borrow().draw_aimline(&mut gs, angle);
+                    }
+                    None => painter.borrow().draw_basic(&gs, true),
                 }
-                ms.vx = dx / dx.hypot(dy);
-                ms.vy = dy / dx.hypot(dy);
-
-                ms.draw_aimline();
             }
         })
     };
 
     let start_aimline = {
-        clone_all![is_draw_aimline, is_moving, is_game_over, draw_aimline];
-        Callback::from(move |e| {
-            if !*is_moving && !*is_game_over {
+        clone_all![
+            is_draw_aimline,
+            is_moving,
+            is_game_over,
+            is_replaying,
+            is_editing,
+            draw_aimline,
+            aim_angle,
+            precision_drag,
+            canvas_ref,
+            game_state,
+        ];
+        Callback::from(move |event: PointerEvent| {
+            if !*is_moving && !*is_game_over && !*is_replaying && !*is_editing {
                 *is_draw_aimline.borrow_mut() = true;
-                draw_aimline.emit(e);
+                // 离得远就是精细拖动
+                *precision_drag.borrow_mut() = precision_drag_from(
+                    &event,
+                    &canvas_ref,
+                    &game_state.borrow(),
+                    *aim_angle.borrow(),
+                );
+                draw_aimline.emit(event);
             }
         })
     };
 
     let cancel_aimline = {
-        clone_all![is_draw_aimline];
-        Callback::from(move |_| *is_draw_aimline.borrow_mut() = false)
+        clone_all![is_draw_aimline, precision_drag];
+        Callback::from(move |_| {
+            *is_draw_aimline.borrow_mut() = false;
+            *precision_drag.borrow_mut() = None;
+        })
     };
 
-    // 点击
-    let onclick = {
+    // 一轮结束：存档、记分，不管是播完的还是直接跑完的
+    let end_turn = {
         clone_all![
+            game_state,
+            painter,
             is_moving,
             is_game_over,
-            map_status,
-            simulation_interval,
-            n_balls,
-            n_balls_to_show,
             level,
+            n_balls_to_show,
+            score_rank,
+            editor,
+            can_undo,
+        ];
+        Callback::from(move |_: ()| {
+            let gs = game_state.borrow();
+            // 试玩的不存
+            if editor.borrow().is_none() {
+                storage::save_game(&gs);
+                // 关卡的成绩不和随机的比
+                if gs.is_game_over && gs.layout.is_none() {
+                    score_rank.set(storage::record_score(ScoreEntry::new(&gs)));
+                }
+                if let Some(i) = gs
+                    .layout
+                    .as_ref()
+                    .filter(|_| gs.is_cleared)
+                    .and_then(campaign_index)
+                {
+                    storage::record_campaign(CAMPAIGN[i].0);
+                }
+                if let Some(date) = date_of(&gs).filter(|_| gs.is_game_over) {
+                    storage::record_daily(DailyResult::new(date, &gs));
+                }
+            }
+            painter.borrow().draw_basic(&gs, true);
+            is_moving.set(false);
+            level.set(gs.level);
+            n_balls_to_show.set(gs.n_balls);
+            is_game_over.set(gs.is_game_over);
+            can_undo.set(!gs.is_game_over);
+        })
+    };
+
+    // 出手
+    let shoot = {
+        clone_all![
+            is_moving,
+            is_game_over,
+            game_state,
+            painter,
+            simulation_loop,
+            n_balls_to_show,
             v,
+            is_replaying,
+            stepper,
+            fast_forward,
+            is_fast_forward,
+            recall_requested,
+            editor,
+            is_editing,
+            undo_snapshot,
+            can_undo,
+            end_turn,
+        ];
+        Callback::from(move |angle: f64| {
+            if *is_moving || *is_game_over || *is_replaying || *is_editing {
+                return;
+            }
+            let before = game_state.borrow().clone();
+            if !game_state.borrow_mut().shoot(angle) {
+                return;
+            }
+            // 试玩的和关卡、每日挑战都不能悔
+            *undo_snapshot.borrow_mut() =
+                (editor.borrow().is_none() && before.allows_undo()).then_some(before);
+            can_undo.set(false);
+
+            is_moving.set(true);
+            stepper.borrow_mut().reset();
+            *fast_forward.borrow_mut() = false;
+            is_fast_forward.set(false);
+            *recall_requested.borrow_mut() = false;
+
+            *simulation_loop.borrow_mut() = {
+                clone_all![
+                    game_state,
+                    painter,
+                    n_balls_to_show,
+                    v,
+                    stepper,
+                    fast_forward,
+                    recall_requested,
+                    end_turn,
+                ];
+                Some(FrameLoop::start(move |dt| {
+                    let mut v = *v.borrow();
+                    if *fast_forward.borrow() {
+                        v *= FAST_FORWARD;
+                    }
+                    let outcome = {
+                        // 保险起见，万一上一个没跑完
+                        let Ok(mut gs) = game_state.try_borrow_mut() else {
+                            return true;
+                        };
+                        let gs = &mut *gs;
+                        let mut stepper = stepper.borrow_mut();
+                        let outcome = if std::mem::take(&mut *recall_requested.borrow_mut()) {
+                            stepper.reset();
+                            gs.recall()
+                        } else {
+                            stepper.advance(gs, v, dt)
+                        };
+                        painter.borrow().draw_moving(gs, &stepper);
+                        if outcome.new_balls > 0 {
+                            n_balls_to_show.set(gs.n_balls);
+                        }
+                        outcome
+                    };
+                    if outcome.turn_done {
+                        end_turn.emit(());
+                        return false;
+                    }
+                    true
+                }))
+            };
+        })
+    };
+
+    // 点击
+    let onclick = {
+        clone_all![
+            game_state,
+            painter,
             canvas_ref,
             is_draw_aimline,
+            editor,
+            is_editing,
+            layout_text,
+            shoot,
+            precision_drag,
         ];
         Callback::from(move |event: PointerEvent| {
             *is_draw_aimline.borrow_mut() = false;
-            if *is_moving || *is_game_over {
+            let drag = precision_drag.borrow_mut().take();
+            if *is_editing {
+                if let Some(editor) = editor.borrow_mut().as_mut() {
+                    let (x, y) = board_point(&event, &canvas_ref, editor.layout.mw);
+                    editor.click(x, y);
+                    editor.draw(&painter.borrow());
+                    layout_text.set(editor.layout.to_string());
+                }
+                return;
+            }
+            let aim = pointer_aim(&event, &canvas_ref, &game_state.borrow(), drag, min_angle);
+            if let Some(angle) = aim {
+                shoot.emit(angle);
+            }
+        })
+    };
+
+    // 编辑
+    let editor_onkeydown = {
+        clone_all![editor, is_editing, painter, layout_text];
+        Callback::from(move |event: KeyboardEvent| {
+            if !*is_editing {
+                return;
+            }
+            if let Some(editor) = editor.borrow_mut().as_mut() {
+                if editor.key(&event.key()) {
+                    event.prevent_default();
+                    editor.draw(&painter.borrow());
+                    layout_text.set(editor.layout.to_string());
+                }
+            }
+        })
+    };
+
+    let edit = {
+        clone_all![
+            game_state,
+            painter,
+            simulation_loop,
+            is_moving,
+            is_replaying,
+            editor,
+            is_editing,
+            layout_text,
+            n_balls_to_show,
+            end_turn,
+        ];
+        Callback::from(move |_: ()| {
+            if *is_replaying || *is_editing {
                 return;
             }
-            let (x, y) = (event.client_x() as f64, event.client_y() as f64);
+            *simulation_loop.borrow_mut() = None;
+            is_moving.set(false);
+
+            // 没跑完的这一轮直接跑完，和播完的一样结算
+            if editor.borrow().is_none() && game_state.borrow_mut().finish_turn().turn_done {
+                end_turn.emit(());
+            }
+            // 试玩完回来的接着改原来的
+            let mut editor = editor.borrow_mut();
+            let editor = editor.get_or_insert_with(|| Editor::new(game_state.borrow().clone()));
+            editor.draw(&painter.borrow());
+            layout_text.set(editor.layout.to_string());
+            n_balls_to_show.set(editor.layout.n_balls);
+            is_editing.set(true);
+        })
+    };
+
+    let editor_n_balls_onchange = {
+        clone_all![editor, layout_text, n_balls_to_show];
+        Callback::from(move |n_balls: u32| {
+            if let Some(editor) = editor.borrow_mut().as_mut() {
+                editor.layout.n_balls = n_balls;
+                layout_text.set(editor.layout.to_string());
+                n_balls_to_show.set(n_balls);
+            }
+        })
+    };
+
+    let editor_max_shots_onchange = {
+        clone_all![editor, layout_text];
+        Callback::from(move |max_shots| {
+            if let Some(editor) = editor.borrow_mut().as_mut() {
+                editor.layout.max_shots = max_shots;
+                layout_text.set(editor.layout.to_string());
+            }
+        })
+    };
+
+    let editor_import = {
+        clone_all![editor, painter, layout_text, n_balls_to_show];
+        Callback::from(move |layout: Layout| {
+            if let Some(editor) = editor.borrow_mut().as_mut() {
+                painter.borrow_mut().fit(layout.mw, layout.mh);
+                editor.layout = layout;
+                editor.selected = None;
+                editor.draw(&painter.borrow());
+                layout_text.set(editor.layout.to_string());
+                n_balls_to_show.set(editor.layout.n_balls);
+            }
+        })
+    };
+
+    let editor_clear = {
+        clone_all![editor, painter, layout_text];
+        Callback::from(move |_| {
+            if let Some(editor) = editor.borrow_mut().as_mut() {
+                editor.clear();
+                editor.draw(&painter.borrow());
+                layout_text.set(editor.layout.to_string());
+            }
+        })
+    };
+
+    // 试玩
+    let editor_play = {
+        clone_all![
+            game_state,
+            painter,
+            editor,
+            is_editing,
+            is_game_over,
+            n_balls_to_show,
+            level,
+        ];
+        Callback::from(move |_| {
+            let Some(preview) = editor.borrow().as_ref().map(Editor::preview) else {
+                return;
+            };
+            let mut gs = game_state.borrow_mut();
+            *gs = preview;
+            painter.borrow().draw_basic(&gs, true);
+            n_balls_to_show.set(gs.n_balls);
+            level.set(gs.level);
+            is_game_over.set(false);
+            is_editing.set(false);
+        })
+    };
+
+    let exit_editor = {
+        clone_all![
+            game_state,
+            painter,
+            simulation_loop,
+            is_moving,
+            editor,
+            is_editing,
+            is_game_over,
+            n_balls_to_show,
+            level,
+        ];
+        Callback::from(move |_| {
+            let Some(editor) = editor.borrow_mut().take() else {
+                return;
+            };
+            *simulation_loop.borrow_mut() = None;
+            is_moving.set(false);
+            let mut gs = game_state.borrow_mut();
+            *gs = editor.live_state;
+            let mut painter = painter.borrow_mut();
+            painter.fit(gs.mw, gs.mh);
+            painter.draw_basic(&gs, true);
+            n_balls_to_show.set(gs.n_balls);
+            level.set(gs.level);
+            is_game_over.set(gs.is_game_over);
+            is_editing.set(false);
+        })
+    };
 
-            let canvas = canvas_ref
-                .cast::<HtmlCanvasElement>()
-                .expect("canvas_ref not attached");
-            let rect = canvas.get_bounding_client_rect();
-            let ratio = rect.width() / (map_status.borrow().mw as f64 * BLOCK_SIZE);
+    let undo = {
+        clone_all![
+            game_state,
+            painter,
+            undo_snapshot,
+            can_undo,
+            n_balls_to_show,
+            level,
+        ];
+        Callback::from(move |_| {
+            let Some(before) = undo_snapshot.borrow_mut().take() else {
+                return;
+            };
+            let mut gs = game_state.borrow_mut();
+            gs.undo(before);
+            storage::save_game(&gs);
+            painter.borrow().draw_basic(&gs, true);
+            n_balls_to_show.set(gs.n_balls);
+            level.set(gs.level);
+            can_undo.set(false);
+        })
+    };
 
-            let (ox, oy) = (
-                rect.left() + map_status.borrow().start_x * ratio,
-                rect.bottom() - BALL_R * ratio,
-            );
+    // 快进
+    let toggle_fast_forward = {
+        clone_all![fast_forward, is_fast_forward];
+        Callback::from(move |_: ()| {
+            let mut fast_forward = fast_forward.borrow_mut();
+            *fast_forward = !*fast_forward;
+            is_fast_forward.set(*fast_forward);
+        })
+    };
 
-            let (dx, dy) = (x - ox, y - oy);
-            if dy > -2.0 * BALL_R * ratio {
+    // 键盘和手柄
+    let can_aim = !*is_moving
+        && !*is_game_over
+        && !*is_replaying
+        && !*is_editing
+        && *resource_state == FULL_RESOURCE;
+    let aim_at = {
+        clone_all![aim_angle, game_state, painter];
+        Callback::from(move |angle: f64| {
+            let angle = clamp_aim(angle, min_angle);
+            *aim_angle.borrow_mut() = angle;
+            if let Ok(mut gs) = game_state.try_borrow_mut() {
+                painter.borrow().draw_aimline(&mut gs, angle);
+            }
+        })
+    };
+    let shoot_aimed = {
+        clone_all![aim_angle];
+        shoot.reform(move |_: ()| *aim_angle.borrow())
+    };
+    let onkeydown = {
+        clone_all![
+            aim_angle,
+            aim_at,
+            shoot_aimed,
+            toggle_fast_forward,
+            is_moving,
+            is_editing
+        ];
+        Callback::from(move |event: KeyboardEvent| {
+            if *is_editing {
+                editor_onkeydown.emit(event);
                 return;
             }
+            let step = if event.shift_key() {
+                FINE_AIM_STEP
+            } else {
+                COARSE_AIM_STEP
+            };
+            let angle = *aim_angle.borrow();
+            match event.key().as_str() {
+                "ArrowLeft" if can_aim => aim_at.emit(angle + step),
+                "ArrowRight" if can_aim => aim_at.emit(angle - step),
+                " " | "Enter" if can_aim => shoot_aimed.emit(()),
+                "f" | "F" if *is_moving => toggle_fast_forward.emit(()),
+                _ => return,
+            }
+            event.prevent_default();
+        })
+    };
+    let gamepad_connected = use_state(gamepad::is_connected);
+    {
+        clone_all![gamepad_connected];
+        use_effect_with((), move |_| {
+            let listener = gamepad::ConnectionListener::start(move |connected| {
+                gamepad_connected.set(connected);
+            });
+            move || drop(listener)
+        });
+    }
+    // 手柄只有插拔的事件，插着又能瞄准的时候每帧读一次；aim_at 用到了最小角度，
+    // 改了要换新的
+    {
+        clone_all![aim_at, shoot_aimed];
+        let can_aim = can_aim && *gamepad_connected;
+        use_effect_with((can_aim, min_angle), move |&(can_aim, _)| {
+            // 按着 A 进来的不算，要松开再按
+            let mut was_firing = true;
+            let mut last_aim = None;
+            let gamepad_loop = can_aim.then(|| {
+                FrameLoop::start(move |_| {
+                    let Some(input) = gamepad::poll() else {
+                        return true;
+                    };
+                    if let Some(aim) = input.aim.filter(|&aim| Some(aim) != last_aim) {
+                        aim_at.emit(aim);
+                    }
+                    last_aim = input.aim;
+                    if input.fire && !was_firing {
+                        shoot_aimed.emit(());
+                        return false;
+                    }
+                    was_firing = input.fire;
+                    true
+                })
+            });
+            move || drop(gamepad_loop)
+        });
+    }
 
-            map_status.borrow_mut().vx = dx / dx.hypot(dy);
-            map_status.borrow_mut().vy = dy / dx.hypot(dy);
+    // 收球，下一帧由模拟循环处理
+    let recall = {
+        clone_all![recall_requested];
+        Callback::from(move |_| *recall_requested.borrow_mut() = true)
+    };
 
-            is_moving.set(true);
-            map_status.borrow_mut().moving_balls = vec![];
-            map_status.borrow_mut().n_waiting_bolls = *n_balls.borrow();
+    // 回放
+    let watch_replay = {
+        clone_all![
+            game_state,
+            painter,
+            simulation_loop,
+            replay_player,
+            replay_loop,
+            is_replaying,
+            is_replay_paused,
+            is_moving,
+            n_balls_to_show,
+            level,
+            editor,
+            is_editing,
+            end_turn,
+        ];
+        Callback::from(move |replay: Replay| {
+            *simulation_loop.borrow_mut() = None;
+            is_moving.set(false);
 
-            *simulation_interval.borrow_mut() = {
+            if let Some(editor) = editor.borrow_mut().take() {
+                *game_state.borrow_mut() = editor.live_state;
+                is_editing.set(false);
+            }
+            // 没跑完的这一轮直接跑完，回来时才能接着玩，和播完的一样结算
+            if game_state.borrow_mut().finish_turn().turn_done {
+                end_turn.emit(());
+            }
+            let mut gs = game_state.borrow_mut();
+            let live_state = std::mem::replace(&mut *gs, replay.state_after(0));
+            let mut painter_mut = painter.borrow_mut();
+            painter_mut.fit(replay.mw, replay.mh);
+            painter_mut.draw_basic(&gs, true);
+            n_balls_to_show.set(gs.n_balls);
+            level.set(gs.level);
+
+            *replay_player.borrow_mut() = Some(ReplayPlayer::new(replay, live_state));
+            is_replaying.set(true);
+            is_replay_paused.set(false);
+
+            *replay_loop.borrow_mut() = {
                 clone_all![
-                    map_status,
-                    simulation_interval,
-                    n_balls,
+                    game_state,
+                    painter,
+                    replay_player,
+                    is_replay_paused,
                     n_balls_to_show,
-                    is_moving,
                     level,
-                    v,
                 ];
-                Some(Interval::new(INTERV, move || {
-                    let v = *v.borrow();
-                    // 保险起见，万一上一个没跑完
-                    if let Some(ms) = map_status.try_borrow_mut().ok().as_deref_mut() {
-                        let (n_new_balls, done) = ms.simulate_moving(v);
-                        if n_new_balls > 0 {
-                            *n_balls.borrow_mut() += n_new_balls;
-                            n_balls_to_show.set(*n_balls.borrow());
-                        }
-                        if done {
-                            is_moving.set(false);
-                            *simulation_interval.borrow_mut() = None;
-                            level.set(*level + 1);
-                        }
+                Some(FrameLoop::start(move |dt| {
+                    let (Ok(mut player), Ok(mut gs)) =
+                        (replay_player.try_borrow_mut(), game_state.try_borrow_mut())
+                    else {
+                        return true;
+                    };
+                    let Some(player) = player.as_mut() else {
+                        return false;
+                    };
+                    if player.is_paused {
+                        return true;
+                    }
+                    let outcome = player.tick(&mut gs, dt);
+                    if gs.is_moving() {
+                        painter.borrow().draw_moving(&gs, &player.stepper);
+                    } else {
+                        painter.borrow().draw_basic(&gs, true);
                     }
+                    if outcome.new_balls > 0 {
+                        n_balls_to_show.set(gs.n_balls);
+                    }
+                    if outcome.turn_done {
+                        level.set(gs.level);
+                    }
+                    if player.is_paused {
+                        is_replay_paused.set(true);
+                    }
+                    true
                 }))
             };
         })
     };
 
+    let replay_toggle_pause = {
+        clone_all![replay_player, is_replay_paused];
+        Callback::from(move |_| {
+            if let Some(player) = replay_player.borrow_mut().as_mut() {
+                player.is_paused = !player.is_paused;
+                is_replay_paused.set(player.is_paused);
+            }
+        })
+    };
+
+    let replay_step_turn = {
+        clone_all![replay_player, game_state, painter, n_balls_to_show, level];
+        Callback::from(move |_| {
+            let mut gs = game_state.borrow_mut();
+            if let Some(player) = replay_player.borrow_mut().as_mut() {
+                player.step_turn(&mut gs);
+                painter.borrow().draw_basic(&gs, true);
+                n_balls_to_show.set(gs.n_balls);
+                level.set(gs.level);
+            }
+        })
+    };
+
+    let replay_jump_to = {
+        clone_all![replay_player, game_state, painter, n_balls_to_show, level];
+        Callback::from(move |to_level| {
+            let mut gs = game_state.borrow_mut();
+            if let Some(player) = replay_player.borrow_mut().as_mut() {
+                player.jump_to(to_level, &mut gs);
+                painter.borrow().draw_basic(&gs, true);
+                n_balls_to_show.set(gs.n_balls);
+                level.set(gs.level);
+            }
+        })
+    };
+
+    let replay_v_onchange = {
+        clone_all![replay_player];
+        Callback::from(move |new_v| {
+            if let Some(player) = replay_player.borrow_mut().as_mut() {
+                player.v = new_v;
+            }
+        })
+    };
+
+    let exit_replay = {
+        clone_all![
+            game_state,
+            painter,
+            replay_player,
+            replay_loop,
+            is_replaying,
+            is_game_over,
+            n_balls_to_show,
+            level,
+        ];
+        Callback::from(move |_| {
+            *replay_loop.borrow_mut() = None;
+            let Some(player) = replay_player.borrow_mut().take() else {
+                return;
+            };
+            let mut gs = game_state.borrow_mut();
+            *gs = player.live_state;
+            storage::save_game(&gs);
+            let mut painter = painter.borrow_mut();
+            painter.fit(gs.mw, gs.mh);
+            painter.draw_basic(&gs, true);
+            n_balls_to_show.set(gs.n_balls);
+            level.set(gs.level);
+            is_game_over.set(gs.is_game_over);
+            is_replaying.set(false);
+        })
+    };
+
+    let watch_last_game = {
+        clone_all![game_state, watch_replay];
+        Callback::from(move |_| {
+            let replay = game_state.borrow().replay();
+            watch_replay.emit(replay);
+        })
+    };
+
     // 载入图片
     let ball_img_onload = {
-        clone_all![map_status, resource_state];
+        clone_all![painter, resource_state];
         Callback::from(move |event: Event| {
             let ball: HtmlImageElement = event.target_unchecked_into();
-            map_status.borrow_mut().img = Some(ball);
+            painter.borrow_mut().img = Some(ball);
             resource_state.set(*resource_state | 1);
         })
     };
     let mickey_img_onload = {
-        clone_all![map_status, resource_state];
+        clone_all![painter, resource_state];
         Callback::from(move |event: Event| {
             let mickey: HtmlImageElement = event.target_unchecked_into();
-            map_status.borrow_mut().mickey = Some(mickey);
+            painter.borrow_mut().mickey = Some(mickey);
             resource_state.set(*resource_state | 2);
         })
     };
 
+    // 载入关卡
+    let start_game = {
+        clone_all![
+            is_game_over,
+            is_moving,
+            simulation_loop,
+            seed,
+            mw,
+            mh,
+            resume,
+            level_loads,
+            level_error,
+        ];
+        Callback::from(move |gs: GameState| {
+            *simulation_loop.borrow_mut() = None;
+            is_moving.set(false);
+            is_game_over.set(false);
+            level_error.set(None);
+            mw.set(gs.mw);
+            mh.set(gs.mh);
+            seed.set(gs.seed);
+            *resume.borrow_mut() = Some(gs);
+            level_loads.set(*level_loads + 1);
+        })
+    };
+    let start_layout = start_game.reform(|(layout, rules): (Layout, Rules)| {
+        GameState::from_layout(layout, random_seed(), rules)
+    });
+    let play_daily = start_game.reform(|_| GameState::daily(&utc_date(0)));
+
+    let level_onload = {
+        clone_all![prefs];
+        start_layout.reform(move |layout| (layout, prefs.rules()))
+    };
+
+    // 谜题和闯关，规则都是固定的
+    let puzzle_onselect = {
+        clone_all![start_layout];
+        Callback::from(move |i| {
+            if let Some(layout) = puzzle(i) {
+                start_layout.emit((layout, Rules::default()));
+            }
+        })
+    };
+    let campaign_onselect = {
+        clone_all![start_layout];
+        Callback::from(move |i| {
+            if let Some(layout) = campaign_level(i) {
+                start_layout.emit((layout, Rules::default()));
+            }
+        })
+    };
+    let retry_level = {
+        clone_all![game_state, start_layout];
+        Callback::from(move |_| {
+            let gs = game_state.borrow();
+            if let Some(layout) = gs.layout.clone() {
+                start_layout.emit((layout, gs.rules));
+            }
+        })
+    };
+
     //重开
-    let restart_cb = {
-        clone_all![is_game_over];
+    let new_game = {
+        clone_all![is_game_over, is_moving, simulation_loop, seed, level_error];
         Callback::from(move |_| {
+            level_error.set(None);
+            storage::clear_game();
+            *simulation_loop.borrow_mut() = None;
+            is_moving.set(false);
+            seed.set(random_seed());
             is_game_over.set(false);
         })
     };
+    let restart_cb = new_game.reform(|_| ());
 
     // 初始化
     {
-        clone_all![canvas_ref, map_status, n_balls, n_balls_to_show, level];
+        clone_all![
+            canvas_ref,
+            game_state,
+            painter,
+            n_balls_to_show,
+            level,
+            replay_player,
+            replay_loop,
+            is_replaying,
+            editor,
+            is_editing,
+            resume,
+            undo_snapshot,
+            can_undo,
+        ];
+        let is_game_over = *is_game_over;
+        let rules = prefs.rules();
         use_effect_with(
-            (canvas_ref, *mw, *mh, *is_game_over, *resource_state),
-            move |(canvas_ref, mw, mh, is_game_over, resource_state)| {
+            (canvas_ref, *mw, *mh, *seed, *level_loads, *resource_state),
+            move |(canvas_ref, mw, mh, seed, _, resource_state)| {
                 if *resource_state != FULL_RESOURCE {
                     return;
                 }
 
-                if *is_game_over {
+                if is_game_over {
                     return;
                 }
                 let (mw, mh) = (*mw, *mh);
@@ -654,110 +1187,257 @@ pub fn game(props: &Props) -> Html {
                     return;
                 };
 
-                let w = mw as u32 * BLOCK_SIZE as u32;
-                let h = mh as u32 * BLOCK_SIZE as u32;
-                canvas.set_width(w);
-                canvas.set_height(h);
-
-                let ctx = CanvasRenderingContext2d::from(JsValue::from(
-                    canvas.get_context("2d").unwrap(),
-                ));
-
-                ctx.set_fill_style(&JsValue::from_str(BG_COLOR));
-                ctx.set_font("45px  sans-serif");
-                ctx.set_text_baseline("middle");
-                ctx.fill_rect(0.0, 0.0, w as f64, h as f64);
-
-                ctx.set_line_width(5.0);
-                ctx.set_line_dash(
-                    &[20.0, 15.0]
-                        .into_iter()
-                        .map(JsValue::from_f64)
-                        .collect::<Array>()
-                        .into(),
-                )
-                .unwrap();
-
-                let mut ms = map_status.borrow_mut();
-                ms.ctx = Some(ctx);
-                ms.moving_balls = vec![];
-                ms.block_map = vec![vec![0; mw]; mh].into();
-                ms.mw = mw;
-                ms.mh = mh;
-                ms.waiting_next = 0;
-                ms.start_x = mw as f64 * BLOCK_SIZE / 2.0;
-                if ms.img.is_some() {
-                    for i in 0..ms.mh / 2 {
-                        ms.update_blocks_and_check_game_over(i + 1);
-                    }
-                }
+                // 换了新局，回放也就不看了，编辑器也关掉
+                *replay_loop.borrow_mut() = None;
+                *replay_player.borrow_mut() = None;
+                is_replaying.set(false);
+                *editor.borrow_mut() = None;
+                is_editing.set(false);
+                *undo_snapshot.borrow_mut() = None;
+                can_undo.set(false);
+
+                // 不用先点一下就能用键盘
+                let _ = canvas.focus();
+                let mut painter = painter.borrow_mut();
+                painter.attach(&canvas, mw, mh);
+
+                let mut gs = game_state.borrow_mut();
+                *gs = match resume.borrow_mut().take() {
+                    Some(saved) if (saved.mw, saved.mh, saved.seed) == (mw, mh, *seed) => saved,
+                    _ => GameState::with_rules(mw, mh, *seed, rules),
+                };
+                storage::save_game(&gs);
+                painter.draw_basic(&gs, true);
 
-                *n_balls.borrow_mut() = 1;
-                n_balls_to_show.set(1);
-                level.set(1);
+                n_balls_to_show.set(gs.n_balls);
+                level.set(gs.level);
             },
         );
     }
 
-    // level上涨时重新生成新的一排
-    {
-        clone_all![level, map_status, is_game_over];
-        use_effect_with(*level, move |level| {
-            if *level == 1 || *is_game_over {
-                return;
-            }
-            let n_rank = map_status.borrow().mh / 2 + *level as usize - 1;
-            if map_status
-                .borrow_mut()
-                .update_blocks_and_check_game_over(n_rank)
-            {
-                is_game_over.set(true);
-            }
-        });
-    }
+    let (mode, rules) = {
+        let gs = game_state.borrow();
+        (gs.mode, gs.rules)
+    };
+    let undos_left = prefs
+        .undo_budget
+        .saturating_sub(game_state.borrow().n_undos);
+    let show_undo = *can_undo
+        && undos_left > 0
+        && !*is_moving
+        && !*is_replaying
+        && editor.borrow().is_none()
+        && game_state.borrow().allows_undo();
+    let (goal, max_shots, stars, is_cleared) = {
+        let gs = game_state.borrow();
+        (
+            gs.goal(),
+            gs.max_shots(),
+            gs.stars() as usize,
+            gs.is_cleared,
+        )
+    };
+    // 每日挑战，按开局那天算
+    let daily = date_of(&game_state.borrow()).map(|date| {
+        let streak = streak(&storage::load_daily());
+        let result = DailyResult::new(date, &game_state.borrow());
+        (streak, summary(&result, streak))
+    });
+    // 过了才有下一关
+    let next_level = {
+        let gs = game_state.borrow();
+        let layout = gs.layout.as_ref().filter(|_| *is_game_over && is_cleared);
+        let next_campaign = layout
+            .and_then(campaign_index)
+            .map(|i| i + 1)
+            .filter(|&i| i < CAMPAIGN.len());
+        let next_puzzle = layout
+            .and_then(puzzle_index)
+            .map(|i| i + 1)
+            .filter(|&i| i < PUZZLES.len());
+        match (next_campaign, next_puzzle) {
+            (Some(i), _) => Some(campaign_onselect.reform(move |_| i)),
+            (None, Some(i)) => Some(puzzle_onselect.reform(move |_| i)),
+            (None, None) => None,
+        }
+    };
+    let (mut view_mw, max_level) = replay_player.borrow().as_ref().map_or((*mw, 1), |player| {
+        (player.replay.mw, player.replay.shots.len() as u32 + 1)
+    });
+    let (is_testing, editor_n_balls, editor_max_shots) = match editor.borrow().as_ref() {
+        Some(editor) => {
+            view_mw = editor.layout.mw;
+            (!*is_editing, editor.layout.n_balls, editor.layout.max_shots)
+        }
+        None => (false, 0, None),
+    };
 
     html! {
         <div
             class={classes!("game-container", props.is_full.then_some("full"))}
-            style={format!("max-width: {}px", 300.max(*mw * 50))}
+            style={format!("max-width: {}px", 300.max(view_mw * 50))}
         >
             <div class={classes!("header", props.is_full.then_some("full"))}>
                 <div>
                     <img id="ballImage" src="static/ball.png" onload={ball_img_onload} />
                     <span id="nBall">{ *n_balls_to_show }</span>
                 </div>
-                <span id="level">{ "level " } { *level }</span>
+                if let Some(max_shots) = max_shots {
+                    <span id="level">{ format!("shots {}/{}", *level - 1, max_shots) }</span>
+                } else if let Some(goal) = goal {
+                    <span class="goal">{ goal_text(goal) }</span>
+                    <span id="level">{ "level " } { *level }</span>
+                } else if let Some((streak, _)) = &daily {
+                    <span class="goal">{ format!("daily 🔥{streak}") }</span>
+                    <span id="level">{ "level " } { *level }</span>
+                } else {
+                    <span id="level">{ "level " } { *level }</span>
+                }
             </div>
             <img id="mickeyImage" src="static/mickey.png" onload={mickey_img_onload} />
             <canvas
                 ref={canvas_ref}
+                tabindex="0"
+                {onkeydown}
                 onpointerdown={start_aimline}
                 onpointercancel={cancel_aimline}
                 onpointermove={draw_aimline}
                 onpointerup={onclick}
             />
+            if *is_moving && !*is_replaying {
+                <div class="turn-bar">
+                    <button
+                        class={classes!((*is_fast_forward).then_some("active"))}
+                        title="fast forward"
+                        onclick={toggle_fast_forward.reform(|_| ())}
+                    >
+                        { "⏩" }
+                    </button>
+                    <button title="recall all balls" onclick={recall}>{ "⤓" }</button>
+                </div>
+            }
+            if show_undo {
+                <div class="turn-bar">
+                    <button title="take back the last shot" onclick={undo}>
+                        { format!("↶ undo ({undos_left} left)") }
+                    </button>
+                </div>
+            }
+            if *is_replaying {
+                <ReplayBar
+                    is_paused={*is_replay_paused}
+                    level={*level}
+                    {max_level}
+                    toggle_pause={replay_toggle_pause}
+                    step_turn={replay_step_turn}
+                    jump_to={replay_jump_to}
+                    v_onchange={replay_v_onchange}
+                    exit={exit_replay}
+                />
+            }
+            if let Some(err) = &*level_error {
+                <div class="level-error">{ "invalid level: " } { err }</div>
+            }
+            if *is_editing {
+                <EditorBar
+                    text={(*layout_text).clone()}
+                    n_balls={editor_n_balls}
+                    n_balls_onchange={editor_n_balls_onchange}
+                    max_shots={editor_max_shots}
+                    max_shots_onchange={editor_max_shots_onchange}
+                    import={editor_import}
+                    clear={editor_clear}
+                    play={editor_play}
+                    exit={exit_editor.clone()}
+                />
+            }
+            if is_testing {
+                <div class="turn-bar">
+                    if *is_game_over {
+                        <span class="test-over">{ "game over" }</span>
+                    }
+                    <button title="back to the editor" onclick={edit.reform(|_| ())}>{ "✎" }</button>
+                    <button title="leave the editor" onclick={exit_editor.reform(|_| ())}>{ "✕" }</button>
+                </div>
+            }
             if *resource_state != FULL_RESOURCE {
                 <div class="loading-hint">
                     { "Loading..." }
                 </div>
             }
             <Settings
-                v={*v.borrow()}
-                {v_onchange}
+                speed={prefs.speed}
+                {speed_onchange}
+                special_chance={prefs.special_chance}
+                {special_chance_onchange}
+                special_weights={prefs.special_weights}
+                {special_weights_onchange}
+                obstacle_level={prefs.obstacle_level}
+                {obstacle_level_onchange}
+                difficulty={prefs.difficulty}
+                {difficulty_onchange}
+                undo_budget={prefs.undo_budget}
+                min_aim_angle={prefs.min_aim_angle}
+                {min_aim_angle_onchange}
+                {undo_budget_onchange}
                 mw={*mw}
                 {mw_onchange}
                 mh={*mh}
                 {mh_onchange}
+                seed={*seed}
+                {seed_onchange}
+                replay_onload={watch_replay}
+                {level_onload}
+                {new_game}
+                {edit}
+                reset={reset_settings}
             />
-            if *is_game_over {
+            <DailyMenu play={play_daily} />
+            <CampaignMenu select={campaign_onselect} />
+            <PuzzleMenu select={puzzle_onselect} />
+            <Trophy mw={*mw} mh={*mh} {mode} {rules} />
+            if *is_game_over && !*is_replaying && editor.borrow().is_none() {
                 <div class="game-over-mask">
                     <div class="foobar"></div>
-                    <div class="game-over-info" onclick={restart_cb}>
-                        <h3>{ "Game Over" }</h3>
-                        <p>
-                            { "level: " } { *level }
-                            <span id="restart">{ "↻" }</span>
-                        </p>
+                    if let Some(goal) = goal {
+                        <div class="game-over-info">
+                            <h3>{ if is_cleared { "Cleared!" } else { "Failed" } }</h3>
+                            <p class="seed">{ goal_text(goal) }</p>
+                            if let Some(max_shots) = max_shots {
+                                <p class="stars">
+                                    { format!("{}{}", "★".repeat(stars), "☆".repeat(3 - stars)) }
+                                </p>
+                                <p>{ format!("shots: {}/{}", *level - 1, max_shots) }</p>
+                            }
+                            <p class="puzzle-buttons">
+                                <button onclick={retry_level}>{ "↻ retry" }</button>
+                                if let Some(next_level) = next_level {
+                                    <button onclick={next_level}>{ "next ▶" }</button>
+                                }
+                            </p>
+                        </div>
+                    } else {
+                        <div class="game-over-info" onclick={restart_cb}>
+                            <h3>{ "Game Over" }</h3>
+                            <p>
+                                { "level: " } { *level }
+                                <span id="restart">{ "↻" }</span>
+                            </p>
+                            <p class="seed">{ "seed: " } { *seed }</p>
+                            if *score_rank == Some(0) {
+                                <p class="new-record">{ "new record!" }</p>
+                            }
+                        </div>
+                        if let Some((_, text)) = &daily {
+                            <div class="daily-summary">
+                                <textarea readonly=true value={text.clone()} />
+                            </div>
+                        }
+                        <ScoreTable mw={*mw} mh={*mh} {mode} {rules} highlight={*score_rank} />
+                    }
+                    <div class="game-over-replay">
+                        <textarea readonly=true value={game_state.borrow().replay().to_string()} />
+                        <button onclick={watch_last_game}>{ "▶ replay" }</button>
                     </div>
                 </div>
             }
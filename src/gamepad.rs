@@ -0,0 +1,78 @@
+use web_sys::wasm_bindgen::closure::Closure;
+use web_sys::wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton};
+
+/// how far the stick has to be pushed to aim
+const DEAD_ZONE: f64 = 0.3;
+/// window events of a gamepad being plugged in or out
+const EVENTS: [&str; 2] = ["gamepadconnected", "gamepaddisconnected"];
+
+/// What the first connected gamepad is doing.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GamepadInput {
+    /// aim angle of the left stick, from the right to the left like
+    /// `Game`'s aim, `None` when it is not pushed up
+    pub aim: Option<f64>,
+    /// A, or the bottom button of the standard mapping, is pressed
+    pub fire: bool,
+}
+
+/// Reads the first connected gamepad, `None` if there is none.
+pub fn poll() -> Option<GamepadInput> {
+    let gamepads = web_sys::window()?.navigator().get_gamepads().ok()?;
+    let gamepad = gamepads
+        .iter()
+        .find_map(|gamepad| gamepad.dyn_into::<Gamepad>().ok())
+        .filter(Gamepad::connected)?;
+
+    let axes = gamepad.axes();
+    let (x, y) = (
+        axes.get(0).as_f64().unwrap_or(0.0),
+        axes.get(1).as_f64().unwrap_or(0.0),
+    );
+    // 往上推才算，y 轴朝下
+    let aim = (x.hypot(y) > DEAD_ZONE && y < 0.0).then(|| (-y).atan2(x));
+    let fire = gamepad
+        .buttons()
+        .get(0)
+        .dyn_into::<GamepadButton>()
+        .is_ok_and(|button| button.pressed());
+    Some(GamepadInput { aim, fire })
+}
+
+/// Whether a gamepad is connected.
+pub fn is_connected() -> bool {
+    poll().is_some()
+}
+
+/// Calls a closure with [`is_connected`] whenever a gamepad is plugged in or
+/// out, until this is dropped.
+pub struct ConnectionListener {
+    on_change: Closure<dyn Fn()>,
+}
+
+impl ConnectionListener {
+    pub fn start(on_change: impl Fn(bool) + 'static) -> Self {
+        let on_change = Closure::<dyn Fn()>::new(move || on_change(is_connected()));
+        if let Some(window) = web_sys::window() {
+            for event in EVENTS {
+                let _ = window
+                    .add_event_listener_with_callback(event, on_change.as_ref().unchecked_ref());
+            }
+        }
+        Self { on_change }
+    }
+}
+
+impl Drop for ConnectionListener {
+    fn drop(&mut self) {
+        if let Some(window) = web_sys::window() {
+            for event in EVENTS {
+                let _ = window.remove_event_listener_with_callback(
+                    event,
+                    self.on_change.as_ref().unchecked_ref(),
+                );
+            }
+        }
+    }
+}
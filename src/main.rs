@@ -1,9 +1,20 @@
 use game::Game;
-use web_sys::window;
+use web_sys::js_sys::decode_uri_component;
+use web_sys::{window, UrlSearchParams};
 use yew::{function_component, html, use_memo, Html, Renderer};
 
+mod campaign;
+mod daily;
+mod editor;
+mod frame_loop;
 mod game;
+mod gamepad;
+mod painter;
+mod puzzles;
+mod replay;
+mod scores;
 mod settings;
+mod storage;
 
 #[function_component(App)]
 fn app() -> Html {
@@ -23,8 +34,22 @@ fn app() -> Html {
 
         (mw as f64 * hsize / wsize).floor() as usize
     });
+    let seed = use_memo((), |_| {
+        let search = window().unwrap().location().search().ok()?;
+        UrlSearchParams::new_with_str(&search)
+            .ok()?
+            .get("seed")?
+            .parse::<u64>()
+            .ok()
+    });
+    // #level=... 里是关卡，见 engine::Layout
+    let level = use_memo((), |_| {
+        let hash = window().unwrap().location().hash().ok()?;
+        let level = hash.strip_prefix("#level=")?;
+        decode_uri_component(level).ok().map(String::from)
+    });
     html! {
-        <Game {mw} mh={*mh} {is_full} />
+        <Game {mw} mh={*mh} {is_full} seed={*seed} level={(*level).clone()} />
     }
 }
 
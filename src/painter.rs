@@ -0,0 +1,357 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use engine::{BlockShape, Cell, GameState, Laser, BALL_R, BALL_SIZE, BLOCK_SIZE, SPLIT_BALLS};
+use web_sys::js_sys::Array;
+use web_sys::wasm_bindgen::{JsCast, JsValue};
+use web_sys::{window, CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement};
+
+use crate::game::Stepper;
+
+const BLOCK_BORDER: f64 = 6.0;
+
+const MICKEY_W: f64 = 924.0;
+const MICKEY_H: f64 = 864.0;
+
+const BG_COLOR: &str = "#3030ff";
+
+const LASER_COLOR: &str = "#ffe040";
+const PICKUP_COLOR: &str = "#40ff80";
+const ARMOR_COLOR: &str = "#c8c8d8";
+const STEEL_COLOR: &str = "#707080";
+
+/// `block_map` and `block_shapes` of a [`GameState`].
+type Board = (VecDeque<Vec<Cell>>, VecDeque<Vec<BlockShape>>);
+
+/// Everything needed to draw a [`GameState`] on the canvas.
+#[derive(Default)]
+pub struct Painter {
+    ctx: Option<CanvasRenderingContext2d>,
+    /// background and blocks, drawn on a canvas off the page and only
+    /// redrawn when the blocks or their shapes change
+    board: Option<CanvasRenderingContext2d>,
+    /// `block_map` and `block_shapes` the board was drawn for
+    drawn_board: RefCell<Option<Board>>,
+    pub img: Option<HtmlImageElement>,
+    pub mickey: Option<HtmlImageElement>,
+}
+
+impl Painter {
+    /// Draws on `canvas` from now on, sized for a `mw`×`mh` map.
+    pub fn attach(&mut self, canvas: &HtmlCanvasElement, mw: usize, mh: usize) {
+        self.ctx = Some(setup_canvas(canvas, mw, mh));
+
+        let board = window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.create_element("canvas").ok())
+            .and_then(|element| element.dyn_into::<HtmlCanvasElement>().ok());
+        self.board = board.map(|board| setup_canvas(&board, mw, mh));
+        self.drawn_board.take();
+    }
+
+    /// Resizes the canvas for a `mw`×`mh` map.
+    pub fn fit(&mut self, mw: usize, mh: usize) {
+        if let Some(canvas) = self.ctx.as_ref().and_then(|ctx| ctx.canvas()) {
+            self.attach(&canvas, mw, mh);
+        }
+    }
+
+    fn draw_ball(&self, ox: f64, oy: f64) {
+        let (Some(ctx), Some(img)) = (self.ctx.as_ref(), self.img.as_ref()) else {
+            return;
+        };
+        ctx.draw_image_with_html_image_element_and_dw_and_dh(
+            img,
+            ox - BALL_R,
+            oy - BALL_R,
+            BALL_SIZE,
+            BALL_SIZE,
+        )
+        .expect("draw next ball failed");
+    }
+
+    fn block_color(&self, v: i32) -> String {
+        format!(
+            "rgb({}, {}, {})",
+            20 + v % 20 * 13,
+            (80.0 + ((v as f64 / 31.0).sin()) * 80.0) as u8,
+            (110.0 + ((v as f64 / 47.0).cos()) * 80.0) as u8,
+        )
+    }
+
+    /// A square block with a bevelled border, `text` in the middle.
+    fn draw_square(&self, ctx: &CanvasRenderingContext2d, x: f64, y: f64, color: &str, text: &str) {
+        ctx.set_fill_style_str("#e0e0e0");
+        ctx.begin_path();
+        ctx.move_to(x, y);
+        ctx.line_to(x + BLOCK_SIZE, y);
+        ctx.line_to(x, y + BLOCK_SIZE);
+        ctx.fill();
+
+        ctx.set_fill_style_str("#202020");
+        ctx.begin_path();
+        ctx.move_to(x + BLOCK_SIZE, y);
+        ctx.line_to(x, y + BLOCK_SIZE);
+        ctx.line_to(x + BLOCK_SIZE, y + BLOCK_SIZE);
+        ctx.fill();
+
+        ctx.set_fill_style_str(color);
+        ctx.fill_rect(
+            x + BLOCK_BORDER,
+            y + BLOCK_BORDER,
+            BLOCK_SIZE - BLOCK_BORDER - BLOCK_BORDER,
+            BLOCK_SIZE - BLOCK_BORDER - BLOCK_BORDER,
+        );
+        ctx.set_fill_style_str("white");
+        ctx.fill_text(
+            text,
+            x + (BLOCK_SIZE - ctx.measure_text(text).unwrap().width()) / 2.0,
+            y + BLOCK_SIZE / 2.0,
+        )
+        .unwrap();
+    }
+
+    fn draw_block(
+        &self,
+        ctx: &CanvasRenderingContext2d,
+        i: usize,
+        j: usize,
+        cell: Cell,
+        shape: BlockShape,
+    ) {
+        let (x, y) = (j as f64 * BLOCK_SIZE, i as f64 * BLOCK_SIZE);
+        let (cx, cy) = (x + BLOCK_SIZE / 2.0, y + BLOCK_SIZE / 2.0);
+        ctx.set_line_dash(&Array::new()).unwrap();
+        match cell {
+            Cell::Empty => {}
+            Cell::Block { hp } if shape != BlockShape::Square => {
+                let corners = shape.corners((x, y, x + BLOCK_SIZE, y + BLOCK_SIZE));
+
+                ctx.begin_path();
+                for &(cx, cy) in &corners {
+                    ctx.line_to(cx, cy);
+                }
+                ctx.close_path();
+                ctx.set_fill_style_str(&self.block_color(hp));
+                ctx.fill();
+                ctx.set_stroke_style_str("#e0e0e0");
+                ctx.stroke();
+
+                // 数字写在重心
+                let (cx, cy) = corners.iter().fold((0.0, 0.0), |(sx, sy), (cx, cy)| {
+                    (sx + cx / 3.0, sy + cy / 3.0)
+                });
+                let text = hp.to_string();
+                ctx.set_fill_style_str("white");
+                ctx.fill_text(
+                    &text,
+                    cx - ctx.measure_text(&text).unwrap().width() / 2.0,
+                    cy,
+                )
+                .unwrap();
+            }
+            Cell::Block { hp } => {
+                self.draw_square(ctx, x, y, &self.block_color(hp), &hp.to_string());
+            }
+            Cell::Armored { hp, cracked } => {
+                self.draw_square(ctx, x, y, &self.block_color(hp), &hp.to_string());
+                let inset = BLOCK_BORDER * 2.0;
+                ctx.set_stroke_style_str(ARMOR_COLOR);
+                ctx.stroke_rect(
+                    x + inset,
+                    y + inset,
+                    BLOCK_SIZE - inset * 2.0,
+                    BLOCK_SIZE - inset * 2.0,
+                );
+                if cracked {
+                    ctx.begin_path();
+                    ctx.move_to(x + inset, y + inset);
+                    ctx.line_to(cx - 10.0, cy - 25.0);
+                    ctx.line_to(cx - 20.0, cy - 20.0);
+                    ctx.stroke();
+                }
+            }
+            Cell::Steel => {
+                self.draw_square(ctx, x, y, STEEL_COLOR, "");
+                let inset = BLOCK_BORDER * 3.0;
+                ctx.set_stroke_style_str(ARMOR_COLOR);
+                ctx.begin_path();
+                ctx.move_to(x + inset, y + inset);
+                ctx.line_to(x + BLOCK_SIZE - inset, y + BLOCK_SIZE - inset);
+                ctx.move_to(x + BLOCK_SIZE - inset, y + inset);
+                ctx.line_to(x + inset, y + BLOCK_SIZE - inset);
+                ctx.stroke();
+            }
+            Cell::BallPickup => {
+                let Some(img) = self.img.as_ref() else { return };
+                ctx.draw_image_with_html_image_element_and_dw_and_dh(
+                    img,
+                    x + BLOCK_SIZE / 4.0,
+                    y + BLOCK_SIZE / 4.0,
+                    BLOCK_SIZE / 2.0,
+                    BLOCK_SIZE / 2.0,
+                )
+                .expect("draw ball to get failed");
+            }
+            Cell::Laser(laser) => {
+                let r = BLOCK_SIZE / 4.0;
+                ctx.set_stroke_style_str(LASER_COLOR);
+                ctx.begin_path();
+                ctx.arc(cx, cy, r, 0.0, std::f64::consts::TAU).unwrap();
+                if laser != Laser::Column {
+                    ctx.move_to(cx - r, cy);
+                    ctx.line_to(cx + r, cy);
+                }
+                if laser != Laser::Row {
+                    ctx.move_to(cx, cy - r);
+                    ctx.line_to(cx, cy + r);
+                }
+                ctx.stroke();
+            }
+            Cell::Split | Cell::Bounce => {
+                ctx.set_stroke_style_str(PICKUP_COLOR);
+                ctx.begin_path();
+                ctx.arc(cx, cy, BLOCK_SIZE / 4.0, 0.0, std::f64::consts::TAU)
+                    .unwrap();
+                ctx.stroke();
+                let text = if cell == Cell::Split {
+                    format!("×{}", SPLIT_BALLS + 1)
+                } else {
+                    String::from("?")
+                };
+                ctx.set_fill_style_str(PICKUP_COLOR);
+                ctx.fill_text(
+                    &text,
+                    cx - ctx.measure_text(&text).unwrap().width() / 2.0,
+                    cy,
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Draws the path of a shot at `angle`, see [`GameState::shoot`].
+    pub fn draw_aimline(&self, state: &mut GameState, angle: f64) {
+        self.draw_basic(state, true);
+        let Some(ctx) = self.ctx.as_ref() else { return };
+        let path = state.aim_path(angle);
+        ctx.begin_path();
+        for (x, y) in path {
+            ctx.line_to(x, y);
+        }
+        ctx.stroke();
+
+        // 角度，水平向右是 0°
+        let text = format!("{:.1}°", angle.to_degrees());
+        let hh = state.mh as f64 * BLOCK_SIZE;
+        ctx.save();
+        ctx.set_font("30px  sans-serif");
+        ctx.set_text_align("center");
+        ctx.set_fill_style_str("white");
+        let _ = ctx.fill_text(&text, state.start_x, hh - BALL_SIZE - 40.0);
+        ctx.restore();
+    }
+
+    /// Redraws the board layer if the blocks changed since last time.
+    fn update_board(&self, state: &GameState) {
+        let Some(ctx) = self.board.as_ref() else {
+            return;
+        };
+        let mut drawn_board = self.drawn_board.borrow_mut();
+        // 编辑器里只改形状的话格子不变
+        if drawn_board
+            .as_ref()
+            .is_some_and(|(map, shapes)| *map == state.block_map && *shapes == state.block_shapes)
+        {
+            return;
+        }
+
+        let ww = state.mw as f64 * BLOCK_SIZE;
+        let hh = state.mh as f64 * BLOCK_SIZE;
+        ctx.set_fill_style_str(BG_COLOR);
+        ctx.fill_rect(0.0, 0.0, ww, hh);
+        if let Some(mickey) = self.mickey.as_ref() {
+            let mick_w = ww;
+            let mick_h = mick_w * MICKEY_H / MICKEY_W;
+            ctx.draw_image_with_html_image_element_and_dw_and_dh(
+                mickey,
+                0.0,
+                (hh - mick_h) / 2.0,
+                mick_w,
+                mick_h,
+            )
+            .expect("draw mickey failed");
+        }
+        for (i, row) in state.block_map.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                self.draw_block(ctx, i, j, *cell, state.block_shape(i, j))
+            }
+        }
+        *drawn_board = Some((state.block_map.clone(), state.block_shapes.clone()));
+    }
+
+    pub fn draw_basic(&self, state: &GameState, with_start_ball: bool) {
+        let hh = state.mh as f64 * BLOCK_SIZE;
+        let (Some(ctx), Some(board)) = (
+            self.ctx.as_ref(),
+            self.board.as_ref().and_then(|board| board.canvas()),
+        ) else {
+            return;
+        };
+        self.update_board(state);
+        ctx.draw_image_with_html_canvas_element(&board, 0.0, 0.0)
+            .expect("draw board failed");
+        if with_start_ball {
+            self.draw_ball(state.start_x, hh - BALL_R)
+        };
+    }
+
+    /// Outlines the cell at row `i`, column `j`, over what is drawn.
+    pub fn draw_selection(&self, i: usize, j: usize) {
+        let Some(ctx) = self.ctx.as_ref() else { return };
+        ctx.save();
+        ctx.set_stroke_style_str(LASER_COLOR);
+        ctx.stroke_rect(
+            j as f64 * BLOCK_SIZE,
+            i as f64 * BLOCK_SIZE,
+            BLOCK_SIZE,
+            BLOCK_SIZE,
+        );
+        ctx.restore();
+    }
+
+    pub fn draw_moving(&self, state: &GameState, stepper: &Stepper) {
+        self.draw_basic(state, state.n_waiting_balls > 0);
+        stepper.ball_positions(state).for_each(|(x, y)| {
+            self.draw_ball(x, y);
+        });
+    }
+}
+
+/// Sizes `canvas` for a `mw`×`mh` map and prepares its context. Resizing a
+/// canvas resets its context, so this has to run after every resize.
+fn setup_canvas(canvas: &HtmlCanvasElement, mw: usize, mh: usize) -> CanvasRenderingContext2d {
+    let w = mw as u32 * BLOCK_SIZE as u32;
+    let h = mh as u32 * BLOCK_SIZE as u32;
+    canvas.set_width(w);
+    canvas.set_height(h);
+
+    let ctx = CanvasRenderingContext2d::from(JsValue::from(canvas.get_context("2d").unwrap()));
+
+    ctx.set_fill_style_str(BG_COLOR);
+    ctx.set_font("45px  sans-serif");
+    ctx.set_text_baseline("middle");
+    ctx.fill_rect(0.0, 0.0, w as f64, h as f64);
+
+    ctx.set_line_width(5.0);
+    ctx.set_line_dash(
+        &[20.0, 15.0]
+            .into_iter()
+            .map(JsValue::from_f64)
+            .collect::<Array>()
+            .into(),
+    )
+    .unwrap();
+
+    ctx
+}
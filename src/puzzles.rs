@@ -0,0 +1,62 @@
+use engine::Layout;
+use yew::{function_component, html, use_state, Callback, Html, Properties};
+
+/// Puzzles that come with the game, in order, see [`Layout`] for the format.
+pub const PUZZLES: [(&str, &str); 6] = [
+    ("Warm-up", include_str!("../puzzles/01-warm-up.txt")),
+    ("Pockets", include_str!("../puzzles/02-pockets.txt")),
+    ("Funnel", include_str!("../puzzles/03-funnel.txt")),
+    ("Steel gate", include_str!("../puzzles/04-steel-gate.txt")),
+    ("Lasers", include_str!("../puzzles/05-lasers.txt")),
+    ("Fortress", include_str!("../puzzles/06-fortress.txt")),
+];
+
+pub fn puzzle(i: usize) -> Option<Layout> {
+    PUZZLES.get(i)?.1.parse().ok()
+}
+
+/// Where `layout` is in [`PUZZLES`], if it is one of them.
+pub fn puzzle_index(layout: &Layout) -> Option<usize> {
+    (0..PUZZLES.len()).find(|&i| puzzle(i).as_ref() == Some(layout))
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    /// starts the puzzle with this index in [`PUZZLES`]
+    pub select: Callback<usize>,
+}
+
+#[function_component(PuzzleMenu)]
+pub fn puzzle_menu(props: &Props) -> Html {
+    let show_menu = use_state(|| false);
+
+    let toggle_cb = {
+        let show_menu = show_menu.clone();
+        Callback::from(move |_| {
+            show_menu.set(!*show_menu);
+        })
+    };
+
+    html! {
+        <div class="puzzle-menu">
+            <button class="toggle-btn" onclick={toggle_cb}>
+                { "🧩" }
+            </button>
+            if *show_menu {
+                <div class="puzzles">
+                    { for PUZZLES.iter().enumerate().map(|(i, (name, _))| {
+                        let select = props.select.clone();
+                        let show_menu = show_menu.clone();
+                        let onclick = Callback::from(move |_| {
+                            show_menu.set(false);
+                            select.emit(i);
+                        });
+                        html! {
+                            <button {onclick}>{ format!("{}. {}", i + 1, name) }</button>
+                        }
+                    }) }
+                </div>
+            }
+        </div>
+    }
+}
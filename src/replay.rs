@@ -0,0 +1,127 @@
+use engine::{GameState, Replay, StepOutcome};
+use web_sys::{Event, HtmlInputElement, InputEvent};
+use yew::{function_component, html, use_state, Callback, Html, Properties, TargetCast};
+
+use crate::game::Stepper;
+use crate::settings::{speed_to_v, DEFAULT_SPEED};
+
+/// Plays a [`Replay`] into the game state shown by the `Game` component.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    /// number of shots already launched
+    pub next_shot: usize,
+    pub is_paused: bool,
+    pub v: f64,
+    pub stepper: Stepper,
+    /// the game on screen before the replay started, shown again on exit
+    pub live_state: GameState,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay, live_state: GameState) -> Self {
+        Self {
+            replay,
+            next_shot: 0,
+            is_paused: false,
+            v: speed_to_v(DEFAULT_SPEED),
+            stepper: Stepper::default(),
+            live_state,
+        }
+    }
+
+    fn launch_next(&mut self, gs: &mut GameState) -> bool {
+        if !self.replay.launch(self.next_shot, gs) {
+            return false;
+        }
+        self.next_shot += 1;
+        true
+    }
+
+    /// Plays `dt` milliseconds, shooting the next shot once a turn is over.
+    /// Pauses by itself at the end of the replay.
+    pub fn tick(&mut self, gs: &mut GameState, dt: f64) -> StepOutcome {
+        if self.is_paused {
+            return StepOutcome::default();
+        }
+        if !gs.is_moving() && !self.launch_next(gs) {
+            self.is_paused = true;
+            return StepOutcome::default();
+        }
+        self.stepper.advance(gs, self.v, dt)
+    }
+
+    /// Plays the running turn, or the next one, to its end at once.
+    pub fn step_turn(&mut self, gs: &mut GameState) -> StepOutcome {
+        if !gs.is_moving() && !self.launch_next(gs) {
+            return StepOutcome::default();
+        }
+        self.stepper.reset();
+        gs.finish_turn()
+    }
+
+    /// Rebuilds the game as it was when `level` started.
+    pub fn jump_to(&mut self, level: u32, gs: &mut GameState) {
+        let n_shots = (level.max(1) as usize - 1).min(self.replay.shots.len());
+        *gs = self.replay.state_after(n_shots);
+        self.next_shot = n_shots;
+        self.stepper.reset();
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub is_paused: bool,
+    pub level: u32,
+    pub max_level: u32,
+    pub toggle_pause: Callback<()>,
+    pub step_turn: Callback<()>,
+    pub jump_to: Callback<u32>,
+    pub v_onchange: Callback<f64>,
+    pub exit: Callback<()>,
+}
+
+#[function_component(ReplayBar)]
+pub fn replay_bar(props: &Props) -> Html {
+    let speed = use_state(|| DEFAULT_SPEED);
+
+    let v_oninput = {
+        let speed = speed.clone();
+        props.v_onchange.reform(move |event: InputEvent| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            let value = input.value_as_number() as i32;
+            speed.set(value);
+            speed_to_v(value)
+        })
+    };
+
+    let level_onchange = props.jump_to.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number().max(1.0) as u32
+    });
+
+    html! {
+        <div class="replay-bar">
+            <button onclick={props.toggle_pause.reform(|_| ())}>
+                { if props.is_paused { "▶" } else { "⏸" } }
+            </button>
+            <button onclick={props.step_turn.reform(|_| ())}>{ "⏭" }</button>
+            <input
+                type="number"
+                class="level-input"
+                value={props.level.to_string()}
+                min={1}
+                max={props.max_level.to_string()}
+                onchange={level_onchange}
+            />
+            <input
+                type="range"
+                value={speed.to_string()}
+                min={3}
+                max={40}
+                step={1}
+                oninput={v_oninput}
+            />
+            <button onclick={props.exit.reform(|_| ())}>{ "✕" }</button>
+        </div>
+    }
+}
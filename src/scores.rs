@@ -0,0 +1,153 @@
+use engine::{GameMode, GameState, Rules};
+use serde::{Deserialize, Serialize};
+use web_sys::js_sys::Date;
+use yew::{function_component, html, use_state, Callback, Html, Properties};
+
+use crate::storage;
+
+/// entries kept for each board size and mode
+pub const MAX_SCORES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub mw: usize,
+    pub mh: usize,
+    pub mode: GameMode,
+    pub rules: Rules,
+    pub level: u32,
+    pub n_balls: u32,
+    /// `YYYY-MM-DD`
+    pub date: String,
+    pub seed: u64,
+    /// a shot was taken back
+    #[serde(default)]
+    pub used_undo: bool,
+}
+
+impl ScoreEntry {
+    /// The finished game `gs`, played today.
+    pub fn new(gs: &GameState) -> Self {
+        let mut date = String::from(Date::new_0().to_iso_string());
+        date.truncate(10);
+        Self {
+            mw: gs.mw,
+            mh: gs.mh,
+            mode: gs.mode,
+            rules: gs.rules,
+            level: gs.level,
+            n_balls: gs.n_balls,
+            date,
+            seed: gs.seed,
+            used_undo: gs.n_undos > 0,
+        }
+    }
+
+    /// Entries with the same key are ranked against each other.
+    pub fn key(&self) -> (usize, usize, GameMode, Rules) {
+        (self.mw, self.mh, self.mode, self.rules)
+    }
+}
+
+/// The difficulty, and whether specials or obstacles were changed too.
+fn rules_name(rules: &Rules) -> String {
+    let difficulty = rules.difficulty.preset_name().unwrap_or("custom");
+    let defaults = Rules {
+        difficulty: rules.difficulty,
+        ..Rules::default()
+    };
+    if *rules == defaults {
+        difficulty.to_string()
+    } else {
+        format!("{difficulty}, custom rules")
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct TableProps {
+    pub mw: usize,
+    pub mh: usize,
+    pub mode: GameMode,
+    pub rules: Rules,
+    /// rank of the entry to highlight
+    #[prop_or_default]
+    pub highlight: Option<usize>,
+}
+
+#[function_component(ScoreTable)]
+pub fn score_table(props: &TableProps) -> Html {
+    let scores: Vec<ScoreEntry> = storage::load_scores()
+        .into_iter()
+        .filter(|e| e.key() == (props.mw, props.mh, props.mode, props.rules))
+        .collect();
+
+    html! {
+        <div class="score-table">
+            <div class="score-title">
+                { format!(
+                    "{}×{} {} · {}",
+                    props.mh,
+                    props.mw,
+                    props.mode.name(),
+                    rules_name(&props.rules),
+                ) }
+            </div>
+            if scores.is_empty() {
+                <div class="score-empty">{ "no records yet" }</div>
+            } else {
+                <table>
+                    <tr>
+                        <th>{ "#" }</th>
+                        <th>{ "level" }</th>
+                        <th>{ "balls" }</th>
+                        <th>{ "date" }</th>
+                        <th>{ "seed" }</th>
+                    </tr>
+                    { for scores.iter().enumerate().map(|(i, e)| html! {
+                        <tr class={(props.highlight == Some(i)).then_some("highlight")}>
+                            <td>{ i + 1 }</td>
+                            <td>
+                                { e.level }
+                                if e.used_undo {
+                                    <span class="used-undo" title="with undo">{ "↶" }</span>
+                                }
+                            </td>
+                            <td>{ e.n_balls }</td>
+                            <td>{ &e.date }</td>
+                            <td>{ e.seed }</td>
+                        </tr>
+                    }) }
+                </table>
+            }
+        </div>
+    }
+}
+
+#[function_component(Trophy)]
+pub fn trophy(props: &TableProps) -> Html {
+    let show_scores = use_state(|| false);
+
+    let toggle_cb = {
+        let show_scores = show_scores.clone();
+        Callback::from(move |_| {
+            show_scores.set(!*show_scores);
+        })
+    };
+
+    html! {
+        <div class="trophy">
+            <button class="toggle-btn" onclick={toggle_cb}>
+                { "🏆" }
+            </button>
+            if *show_scores {
+                <div class="scores">
+                    <ScoreTable
+                        mw={props.mw}
+                        mh={props.mh}
+                        mode={props.mode}
+                        rules={props.rules}
+                    />
+                </div>
+            }
+        </div>
+    }
+}
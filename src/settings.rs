@@ -1,50 +1,292 @@
-use web_sys::{Event, HtmlInputElement, InputEvent};
-use yew::{function_component, html, use_state, Callback, Html, Properties, TargetCast};
+use engine::{
+    DifficultyProfile, Layout, Replay, Rules, SpecialWeights, OBSTACLE_LEVEL, SPECIAL_CHANCE,
+};
+use serde::{Deserialize, Serialize};
+use web_sys::wasm_bindgen::closure::Closure;
+use web_sys::wasm_bindgen::JsCast;
+use web_sys::{
+    Event, FileReader, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement, InputEvent,
+};
+use yew::{
+    function_component, html, use_node_ref, use_state, Callback, Html, Properties, TargetCast,
+    UseStateHandle,
+};
+
+pub const DEFAULT_SPEED: i32 = 10;
+pub const DEFAULT_UNDO_BUDGET: u32 = 3;
+/// degrees, flatter shots take too long to come back
+pub const DEFAULT_MIN_AIM_ANGLE: f64 = 8.0;
+pub const MAX_MIN_AIM_ANGLE: f64 = 60.0;
+
+/// Speed slider position to distance per timer tick.
+pub fn speed_to_v(speed: i32) -> f64 {
+    (speed as f64).powi(3) / 125.0
+}
+
+/// Values of the settings panel kept across visits.
+///
+/// Missing fields fall back to their defaults, so new ones can be added
+/// without breaking what is already stored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// `None` until set by hand, then the size fits the window
+    pub mw: Option<usize>,
+    pub mh: Option<usize>,
+    pub speed: i32,
+    /// used from the next new game on, like `obstacle_level`
+    pub special_chance: f64,
+    pub special_weights: SpecialWeights,
+    /// `None` for no obstacles
+    pub obstacle_level: Option<u32>,
+    pub difficulty: DifficultyProfile,
+    /// shots that can be taken back in a game
+    pub undo_budget: u32,
+    /// degrees above the floor shots can be aimed at least
+    pub min_aim_angle: f64,
+}
+
+impl Preferences {
+    /// Rules of the next new game.
+    pub fn rules(&self) -> Rules {
+        Rules {
+            special_chance: self.special_chance,
+            special_weights: self.special_weights,
+            obstacle_level: self.obstacle_level,
+            difficulty: self.difficulty,
+        }
+    }
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            mw: None,
+            mh: None,
+            speed: DEFAULT_SPEED,
+            special_chance: SPECIAL_CHANCE,
+            special_weights: SpecialWeights::EVEN,
+            obstacle_level: Some(OBSTACLE_LEVEL),
+            difficulty: DifficultyProfile::NORMAL,
+            undo_budget: DEFAULT_UNDO_BUDGET,
+            min_aim_angle: DEFAULT_MIN_AIM_ANGLE,
+        }
+    }
+}
 
 #[derive(Properties, PartialEq)]
 pub struct Props {
-    pub v: f64,
-    pub v_onchange: Callback<f64>,
+    /// position of the speed slider, see [`speed_to_v`]
+    pub speed: i32,
+    pub speed_onchange: Callback<i32>,
+    pub special_chance: f64,
+    pub special_chance_onchange: Callback<f64>,
+    pub special_weights: SpecialWeights,
+    pub special_weights_onchange: Callback<SpecialWeights>,
+    pub obstacle_level: Option<u32>,
+    pub obstacle_level_onchange: Callback<Option<u32>>,
+    pub difficulty: DifficultyProfile,
+    pub difficulty_onchange: Callback<DifficultyProfile>,
+    pub undo_budget: u32,
+    pub undo_budget_onchange: Callback<u32>,
+    /// degrees, see [`Preferences::min_aim_angle`]
+    pub min_aim_angle: f64,
+    pub min_aim_angle_onchange: Callback<f64>,
     pub mw: usize,
     pub mw_onchange: Callback<usize>,
     pub mh: usize,
     pub mh_onchange: Callback<usize>,
+    pub seed: u64,
+    pub seed_onchange: Callback<u64>,
+    pub replay_onload: Callback<Replay>,
+    /// starts a new game on a [`Layout`]
+    pub level_onload: Callback<Layout>,
+    pub new_game: Callback<()>,
+    /// opens the board editor
+    pub edit: Callback<()>,
+    pub reset: Callback<()>,
+}
+
+/// Parses `text` as a [`Layout`] and loads it, or shows why it cannot.
+fn load_level(
+    text: &str,
+    level_onload: &Callback<Layout>,
+    level_error: &UseStateHandle<Option<String>>,
+) {
+    match text.parse::<Layout>() {
+        Ok(layout) => {
+            level_error.set(None);
+            level_onload.emit(layout);
+        }
+        Err(err) => level_error.set(Some(err.to_string())),
+    }
 }
 
 #[function_component(Settings)]
 pub fn settings(props: &Props) -> Html {
-    let mw = use_state(|| props.mw);
-    let mh = use_state(|| props.mh);
-    let v = use_state(|| 10);
     let show_setting = use_state(|| false);
 
-    let v_oninput = {
-        let v = v.clone();
-        props.v_onchange.reform(move |event: InputEvent| {
+    let speed_oninput = props.speed_onchange.reform(|event: InputEvent| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number() as i32
+    });
+
+    let special_chance_oninput = props.special_chance_onchange.reform(|event: InputEvent| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number() / 100.0
+    });
+
+    let special_weight_input = |set: fn(&mut SpecialWeights, u32)| {
+        let special_weights = props.special_weights;
+        props.special_weights_onchange.reform(move |event: Event| {
             let input: HtmlInputElement = event.target_unchecked_into();
-            let value = input.value_as_number();
-            v.set(value as i32);
-            value.powi(3) / 125.0
+            let mut special_weights = special_weights;
+            set(
+                &mut special_weights,
+                input.value_as_number().max(0.0) as u32,
+            );
+            special_weights
         })
     };
+    let laser_weight_onchange = special_weight_input(|w, n| w.laser = n);
+    let split_weight_onchange = special_weight_input(|w, n| w.split = n);
+    let bounce_weight_onchange = special_weight_input(|w, n| w.bounce = n);
+
+    // 空着就是不要三角形
+    let obstacle_level_onchange = props.obstacle_level_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        let level = input.value_as_number();
+        level.is_finite().then_some(level.max(1.0) as u32)
+    });
 
-    let mw_onchange = {
-        let mw = mw.clone();
-        props.mw_onchange.reform(move |event: Event| {
+    // 选了 custom 才显示各项，不是预设的也显示
+    let is_custom = use_state(|| false);
+    let show_difficulty = *is_custom || props.difficulty.preset_name().is_none();
+    let difficulty_preset_onchange = {
+        let is_custom = is_custom.clone();
+        let difficulty_onchange = props.difficulty_onchange.clone();
+        Callback::from(move |event: Event| {
+            let select: HtmlSelectElement = event.target_unchecked_into();
+            let preset = DifficultyProfile::PRESETS
+                .iter()
+                .find(|(name, _)| *name == select.value());
+            is_custom.set(preset.is_none());
+            if let Some((_, profile)) = preset {
+                difficulty_onchange.emit(*profile);
+            }
+        })
+    };
+    // 改一项，其他的不变
+    let difficulty_input = |set: fn(&mut DifficultyProfile, f64)| {
+        let difficulty = props.difficulty;
+        props.difficulty_onchange.reform(move |event: Event| {
             let input: HtmlInputElement = event.target_unchecked_into();
-            let value = (input.value_as_number() as usize).max(4);
-            mw.set(value);
-            value
+            let mut difficulty = difficulty;
+            set(&mut difficulty, input.value_as_number());
+            difficulty.clamped()
         })
     };
+    let min_blocks_onchange = difficulty_input(|d, n| d.min_blocks_every = n as u32);
+    let max_blocks_onchange = difficulty_input(|d, n| d.max_blocks_every = n as u32);
+    let hp_multiplier_onchange = difficulty_input(|d, n| d.hp_multiplier = n);
+    let double_hp_onchange = difficulty_input(|d, n| {
+        d.double_hp_every = n.is_finite().then_some(n as u32);
+    });
+    let pickup_chance_onchange = difficulty_input(|d, n| d.pickup_chance = n / 100.0);
+
+    let undo_budget_onchange = props.undo_budget_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        input.value_as_number().max(0.0) as u32
+    });
+
+    let min_aim_angle_onchange = props.min_aim_angle_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        match input.value_as_number() {
+            angle if angle.is_finite() => angle.clamp(1.0, MAX_MIN_AIM_ANGLE),
+            _ => DEFAULT_MIN_AIM_ANGLE,
+        }
+    });
+
+    let mw_onchange = props.mw_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        (input.value_as_number() as usize).max(4)
+    });
+
+    let mh_onchange = props.mh_onchange.reform(|event: Event| {
+        let input: HtmlInputElement = event.target_unchecked_into();
+        (input.value_as_number() as usize).max(4)
+    });
 
-    let mh_onchange = {
-        let mh = mh.clone();
-        props.mh_onchange.reform(move |event: Event| {
+    let seed_onchange = {
+        let seed_onchange = props.seed_onchange.clone();
+        Callback::from(move |event: Event| {
             let input: HtmlInputElement = event.target_unchecked_into();
-            let value = (input.value_as_number() as usize).max(4);
-            mh.set(value);
-            value
+            if let Ok(seed) = input.value().trim().parse::<u64>() {
+                seed_onchange.emit(seed);
+            }
+        })
+    };
+
+    let replay_ref = use_node_ref();
+    let replay_error = use_state(|| None::<String>);
+    let replay_onclick = {
+        let replay_ref = replay_ref.clone();
+        let replay_error = replay_error.clone();
+        let replay_onload = props.replay_onload.clone();
+        Callback::from(move |_| {
+            let Some(input) = replay_ref.cast::<HtmlInputElement>() else {
+                return;
+            };
+            match input.value().parse::<Replay>() {
+                Ok(replay) => {
+                    replay_error.set(None);
+                    replay_onload.emit(replay);
+                }
+                Err(err) => replay_error.set(Some(err.to_string())),
+            }
+        })
+    };
+
+    let level_ref = use_node_ref();
+    let level_error = use_state(|| None::<String>);
+    let level_onclick = {
+        let level_ref = level_ref.clone();
+        let level_error = level_error.clone();
+        let level_onload = props.level_onload.clone();
+        Callback::from(move |_| {
+            if let Some(input) = level_ref.cast::<HtmlTextAreaElement>() {
+                load_level(&input.value(), &level_onload, &level_error);
+            }
+        })
+    };
+
+    // 读上传的文件
+    let level_file_onchange = {
+        let level_error = level_error.clone();
+        let level_onload = props.level_onload.clone();
+        Callback::from(move |event: Event| {
+            let input: HtmlInputElement = event.target_unchecked_into();
+            let Some(file) = input.files().and_then(|files| files.get(0)) else {
+                return;
+            };
+            let Ok(reader) = FileReader::new() else {
+                return;
+            };
+            let onload = {
+                let reader = reader.clone();
+                let level_error = level_error.clone();
+                let level_onload = level_onload.clone();
+                Closure::once_into_js(move || {
+                    let text = reader.result().ok().and_then(|text| text.as_string());
+                    load_level(&text.unwrap_or_default(), &level_onload, &level_error);
+                })
+            };
+            reader.set_onload(Some(onload.unchecked_ref()));
+            if reader.read_as_text(&file).is_err() {
+                level_error.set(Some(String::from("cannot read the file")));
+            }
+            // 同一个文件改了还能再选
+            input.set_value("");
         })
     };
 
@@ -67,7 +309,7 @@ pub fn settings(props: &Props) -> Html {
                         <input
                             type="number"
                             class="size-input"
-                            value={mh.to_string()}
+                            value={props.mh.to_string()}
                             min={3}
                             onchange={mh_onchange}
                         />
@@ -75,7 +317,7 @@ pub fn settings(props: &Props) -> Html {
                         <input
                             type="number"
                             class="size-input"
-                            value={mw.to_string()}
+                            value={props.mw.to_string()}
                             min={3}
                             onchange={mw_onchange}
                         />
@@ -84,14 +326,170 @@ pub fn settings(props: &Props) -> Html {
                         <label for="speedInput">{ "speed" }</label>
                         <input
                             type="range"
-                            value={v.to_string()}
+                            value={props.speed.to_string()}
                             id="speedInput"
                             min={3}
                             max={40}
                             step={1}
-                            oninput={v_oninput}
+                            oninput={speed_oninput}
+                        />
+                    </div>
+                    <div class="special-setting">
+                        <label for="specialInput">{ "specials" }</label>
+                        <input
+                            type="range"
+                            value={((props.special_chance * 100.0).round() as i32).to_string()}
+                            id="specialInput"
+                            min={0}
+                            max={50}
+                            step={5}
+                            oninput={special_chance_oninput}
+                        />
+                    </div>
+                    <div class="special-weights">
+                        <label>{ "lasers ×" }</label>
+                        <input
+                            type="number"
+                            value={props.special_weights.laser.to_string()}
+                            min={0}
+                            onchange={laser_weight_onchange}
+                        />
+                        <label>{ "splits ×" }</label>
+                        <input
+                            type="number"
+                            value={props.special_weights.split.to_string()}
+                            min={0}
+                            onchange={split_weight_onchange}
+                        />
+                        <label>{ "bounces ×" }</label>
+                        <input
+                            type="number"
+                            value={props.special_weights.bounce.to_string()}
+                            min={0}
+                            onchange={bounce_weight_onchange}
+                        />
+                    </div>
+                    <div class="obstacle-setting">
+                        <label>{ "obstacles from level" }</label>
+                        <input
+                            type="number"
+                            class="obstacle-input"
+                            value={props.obstacle_level.map_or(String::new(), |level| level.to_string())}
+                            min={1}
+                            placeholder="never"
+                            onchange={obstacle_level_onchange}
+                        />
+                    </div>
+                    <div class="difficulty-setting">
+                        <label>{ "difficulty" }</label>
+                        <select onchange={difficulty_preset_onchange}>
+                            { for DifficultyProfile::PRESETS.iter().map(|(name, profile)| html! {
+                                <option
+                                    value={*name}
+                                    selected={!show_difficulty && *profile == props.difficulty}
+                                >
+                                    { name }
+                                </option>
+                            }) }
+                            <option value="custom" selected={show_difficulty}>{ "custom" }</option>
+                        </select>
+                    </div>
+                    if show_difficulty {
+                        <div class="difficulty-custom">
+                            <label>{ "min blocks +1 every" }</label>
+                            <input
+                                type="number"
+                                value={props.difficulty.min_blocks_every.to_string()}
+                                min={1}
+                                onchange={min_blocks_onchange}
+                            />
+                            <label>{ "max blocks +1 every" }</label>
+                            <input
+                                type="number"
+                                value={props.difficulty.max_blocks_every.to_string()}
+                                min={1}
+                                onchange={max_blocks_onchange}
+                            />
+                            <label>{ "hp ×" }</label>
+                            <input
+                                type="number"
+                                value={props.difficulty.hp_multiplier.to_string()}
+                                min={0.1}
+                                step={0.05}
+                                onchange={hp_multiplier_onchange}
+                            />
+                            <label>{ "double hp every" }</label>
+                            <input
+                                type="number"
+                                value={props.difficulty.double_hp_every.map_or(String::new(), |every| every.to_string())}
+                                min={1}
+                                placeholder="never"
+                                onchange={double_hp_onchange}
+                            />
+                            <label>{ "balls %" }</label>
+                            <input
+                                type="number"
+                                value={((props.difficulty.pickup_chance * 100.0).round() as i32).to_string()}
+                                min={0}
+                                max={100}
+                                step={5}
+                                onchange={pickup_chance_onchange}
+                            />
+                        </div>
+                    }
+                    <div class="aim-setting">
+                        <label>{ "min angle °" }</label>
+                        <input
+                            type="number"
+                            value={props.min_aim_angle.to_string()}
+                            min={1}
+                            max={MAX_MIN_AIM_ANGLE.to_string()}
+                            onchange={min_aim_angle_onchange}
+                        />
+                    </div>
+                    <div class="undo-setting">
+                        <label>{ "undos per game" }</label>
+                        <input
+                            type="number"
+                            value={props.undo_budget.to_string()}
+                            min={0}
+                            onchange={undo_budget_onchange}
                         />
                     </div>
+                    <div class="seed-setting">
+                        <label>{ "seed" }</label>
+                        <input
+                            type="text"
+                            inputmode="numeric"
+                            class="seed-input"
+                            value={props.seed.to_string()}
+                            onchange={seed_onchange}
+                        />
+                    </div>
+                    <div class="replay-setting">
+                        <label>{ "replay" }</label>
+                        <input type="text" class="replay-input" ref={replay_ref} />
+                        <button onclick={replay_onclick}>{ "▶" }</button>
+                    </div>
+                    if let Some(err) = &*replay_error {
+                        <div class="replay-error">{ err }</div>
+                    }
+                    <div class="level-setting">
+                        <label>{ "level" }</label>
+                        <textarea class="level-input" placeholder="size 10x16 ..." ref={level_ref} />
+                        <button onclick={level_onclick}>{ "▶" }</button>
+                    </div>
+                    <input type="file" accept=".txt,text/plain" onchange={level_file_onchange} />
+                    if let Some(err) = &*level_error {
+                        <div class="replay-error">{ err }</div>
+                    }
+                    <div class="setting-buttons">
+                        <button onclick={props.new_game.reform(|_| ())}>{ "New game" }</button>
+                        <button onclick={props.edit.reform(|_| ())}>{ "Edit board" }</button>
+                        <button onclick={props.reset.reform(|_| ())}>
+                            { "Reset to defaults" }
+                        </button>
+                    </div>
                 </div>
             }
         </div>
@@ -0,0 +1,104 @@
+use std::cmp::Reverse;
+
+use engine::GameState;
+use gloo_storage::{LocalStorage, Storage};
+
+use crate::daily::DailyResult;
+use crate::scores::{ScoreEntry, MAX_SCORES};
+use crate::settings::Preferences;
+
+const GAME_KEY: &str = "bouncy-ball:game";
+const SETTINGS_KEY: &str = "bouncy-ball:settings";
+const SCORES_KEY: &str = "bouncy-ball:scores";
+const CAMPAIGN_KEY: &str = "bouncy-ball:campaign";
+const DAILY_KEY: &str = "bouncy-ball:daily";
+
+/// The unfinished game saved by [`save_game`], if any.
+pub fn load_game() -> Option<GameState> {
+    LocalStorage::get::<GameState>(GAME_KEY)
+        .ok()
+        .filter(|gs| !gs.is_game_over)
+}
+
+pub fn save_game(gs: &GameState) {
+    if gs.is_game_over {
+        clear_game();
+    } else {
+        // 存不下就算了，不影响玩
+        let _ = LocalStorage::set(GAME_KEY, gs);
+    }
+}
+
+pub fn clear_game() {
+    LocalStorage::delete(GAME_KEY);
+}
+
+pub fn load_settings() -> Preferences {
+    LocalStorage::get(SETTINGS_KEY).unwrap_or_default()
+}
+
+pub fn save_settings(prefs: &Preferences) {
+    let _ = LocalStorage::set(SETTINGS_KEY, prefs);
+}
+
+pub fn clear_settings() {
+    LocalStorage::delete(SETTINGS_KEY);
+}
+
+pub fn load_scores() -> Vec<ScoreEntry> {
+    LocalStorage::get(SCORES_KEY).unwrap_or_default()
+}
+
+/// Adds `entry` to the table of its board and returns its rank there, `None`
+/// if it did not make it.
+pub fn record_score(entry: ScoreEntry) -> Option<usize> {
+    let mut scores = load_scores();
+    let key = entry.key();
+    scores.push(entry.clone());
+    // 同一张表里先按 level，再按球数排
+    scores.sort_by_key(|e| Reverse((e.level, e.n_balls)));
+
+    let mut rank = None;
+    let mut n_same_board = 0;
+    scores.retain(|e| {
+        if e.key() != key {
+            return true;
+        }
+        if *e == entry && rank.is_none() {
+            rank = Some(n_same_board);
+        }
+        n_same_board += 1;
+        n_same_board <= MAX_SCORES
+    });
+    let _ = LocalStorage::set(SCORES_KEY, &scores);
+    rank.filter(|rank| *rank < MAX_SCORES)
+}
+
+/// Names of the campaign levels cleared so far.
+pub fn load_campaign() -> Vec<String> {
+    LocalStorage::get(CAMPAIGN_KEY).unwrap_or_default()
+}
+
+pub fn record_campaign(name: &str) {
+    let mut cleared = load_campaign();
+    if !cleared.iter().any(|c| c == name) {
+        cleared.push(name.to_string());
+        let _ = LocalStorage::set(CAMPAIGN_KEY, &cleared);
+    }
+}
+
+/// Results of the daily games, one per day.
+pub fn load_daily() -> Vec<DailyResult> {
+    LocalStorage::get(DAILY_KEY).unwrap_or_default()
+}
+
+/// Keeps `result` unless the day already has a better one.
+pub fn record_daily(result: DailyResult) {
+    let mut results = load_daily();
+    match results.iter_mut().find(|r| r.date == result.date) {
+        Some(old) if (old.level, old.n_balls) >= (result.level, result.n_balls) => return,
+        Some(old) => *old = result,
+        None => results.push(result),
+    }
+    let _ = LocalStorage::set(DAILY_KEY, &results);
+}